@@ -1,13 +1,17 @@
-use std::fs::File;
-use std::io::{Read, BufReader};
+use intcode::load;
+
+use std::convert::TryInto;
+use std::process;
 
 fn load_input() -> Vec<usize> {
-    let file = File::open("input.txt").unwrap();
-    let mut input = String::new();
-    BufReader::new(file).read_to_string(&mut input).unwrap();
-    input.split(',')
-        .filter_map({ |x| x.parse().ok() })
-        .collect()
+    let program = load::file("input.txt").unwrap_or_else(|e| {
+        eprintln!("input.txt: {}", e);
+        process::exit(1)
+    });
+    program.into_iter().enumerate().map(|(index, value)| value.try_into().unwrap_or_else(|_| {
+        eprintln!("input.txt: negative value {} at index {}", value, index);
+        process::exit(1)
+    })).collect()
 }
 
 struct IntComputer {
@@ -88,6 +92,7 @@ fn main() {
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use intcode::fuzz::{self, Generator, Program, Rng, Stop};
 
     #[test]
     fn test_part1() {
@@ -96,4 +101,28 @@ mod tests {
         assert_eq!(ic.value(0), 3500);
     }
 
+    fn final_memory(program: &[i64]) -> Vec<i64> {
+        let mut ic = IntComputer::load(program.iter().map(|value| *value as usize).collect());
+        ic.execute();
+        ic.state.iter().map(|value| *value as i64).collect()
+    }
+
+    #[test]
+    fn test_matches_intcode() {
+        let generator = Generator::arithmetic(20);
+        for seed in 0..1_000 {
+            let program = generator.generate(&mut Rng::new(seed));
+            let differs = |program: &Program| {
+                let program = program.to_vec();
+                let expected = fuzz::run(&program, &[], 1_000);
+                // Programs that overflow are outside what this interpreter handles.
+                expected.stop == Stop::Halted && final_memory(&program) != expected.memory
+            };
+            if differs(&program) {
+                let minimal = program.shrink(differs).to_vec();
+                panic!("seed {}: {:?}\nintcode: {:?}\naoc2:    {:?}", seed, minimal,
+                    fuzz::run(&minimal, &[], 1_000).memory, final_memory(&minimal));
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -1,31 +1,92 @@
-use intcode::IntComputer;
+use intcode::{ IntComputer, IntcodeError };
+use intcode::control_flow::ControlFlowGraph;
+use intcode::history::History;
+use intcode::load;
+use intcode::profile::Profiler;
+use intcode::selfmod::CodeTracker;
 
-use std::fs::File;
-use std::io::{Read, BufReader};
+use std::env;
+use std::process;
 
 fn load_input() -> Vec<i64> {
-    let file = File::open("input.txt").unwrap();
-    let mut input = String::new();
-    BufReader::new(file).read_to_string(&mut input).unwrap();
-    input.split(',')
-        .filter_map({ |x| x.parse().ok() })
-        .collect()
+    load::file("input.txt").unwrap_or_else(|e| {
+        eprintln!("input.txt: {}", e);
+        process::exit(1)
+    })
 }
 
-fn main() {
-    let mut ic = IntComputer::load(load_input());
-    ic.write(1);
-    ic.execute();
-
-    while let Some(i) = ic.read() {
-        println!("{}", i)
+/// Every output but the last is a self-test result that should be zero.
+/// If one failed, replays the run with history on, rewinds it to the first
+/// failing test and reports where its code came from.
+fn report_failure(program: &[i64], system: i64, outputs: &[i64]) -> Result<(), IntcodeError> {
+    let tests = &outputs[..outputs.len().saturating_sub(1)];
+    let failed = match tests.iter().position(|code| *code != 0) {
+        Some(index) => index,
+        None => return Ok(())
+    };
+    let mut ic = IntComputer::load(program.to_vec());
+    ic.set_history(History::new(100_000));
+    ic.write(system);
+    ic.execute()?;
+    let mut remaining = outputs.len() - failed;
+    let record = ic.step_back_until(|record| record.output.is_some() && {
+        remaining -= 1;
+        remaining == 0
+    });
+    if let Some(record) = record {
+        eprintln!("test {} failed with code {}, output by the instruction at {}",
+            failed, outputs[failed], record.position);
+        if let Some(address) = ic.current_instruction()
+            .and_then(|instruction| instruction.inputs().first().and_then(|input| input.address(ic.relative_base()))) {
+            if let Some(write) = ic.run_back_to_write(address) {
+                eprintln!("  the code was written to [{}] by the instruction at {}", write.address, ic.position());
+            }
+        }
     }
+    Ok(())
+}
+
+fn main() -> Result<(), IntcodeError> {
+    // `--profile` prints a profile of both runs to stderr. `--dot` prints
+    // the control-flow graph of the thermal radiator program to stderr, taken
+    // after its first two instructions, which patch the code from the input.
+    // `--code-writes` summarises each run's writes to executed code.
+    let profile = env::args().any(|arg| arg == "--profile");
+    let dot = env::args().any(|arg| arg == "--dot");
+    let code_writes = env::args().any(|arg| arg == "--code-writes");
+    let program = load_input();
+    let mut profiler = Profiler::new();
 
-    ic = IntComputer::load(load_input());
-    ic.write(5);
-    ic.execute();
+    for system in &[1, 5] {
+        let mut ic = IntComputer::load(program.clone());
+        if profile {
+            ic.set_profiler(Profiler::new());
+        }
+        if code_writes {
+            ic.set_code_tracker(CodeTracker::new());
+        }
+        ic.write(*system);
+        if dot && *system == 5 {
+            ic.execute_for(2)?;
+            eprint!("{}", ControlFlowGraph::new(ic.memory()).to_dot());
+        }
+        ic.execute()?;
+
+        let outputs: Vec<i64> = std::iter::from_fn(|| ic.read()).collect();
+        for i in &outputs {
+            println!("{}", i)
+        }
+        report_failure(&program, *system, &outputs)?;
+        if let Some(tracker) = ic.code_tracker() {
+            eprint!("system {}: {}", system, tracker.summary());
+        }
+        if let Some(run) = ic.profiler() {
+            profiler.merge(run);
+        }
+    }
 
-    while let Some(i) = ic.read() {
-        println!("{}", i)
+    if profile {
+        eprint!("{}", profiler.report(program.len(), 5));
     }
+    Ok(())
 }
@@ -0,0 +1,122 @@
+//! Times the decode cache against plain decoding on the test programs.
+//! Run with `cargo run --release --example bench`.
+
+use intcode::IntComputer;
+use intcode::load;
+
+use std::time::{Duration, Instant};
+
+const QUINE: [i64; 16] = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+const AMPLIFIER: [i64; 29] = [3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,
+    27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
+const AOC7_INPUT: &str = "../aoc7/input.txt";
+// Counts its input down to zero.
+const COUNTDOWN: [i64; 13] = [3,12,1001,12,-1,12,1005,12,2,104,0,99,0];
+
+/// Runs one case with or without the cache and returns the instructions
+/// it executed.
+type Bench = fn(bool) -> u64;
+
+fn machine(program: &[i64], cache: bool) -> IntComputer {
+    let mut ic = IntComputer::load(program.to_vec());
+    ic.set_decode_cache(cache);
+    ic
+}
+
+fn quine(cache: bool) -> u64 {
+    let mut ic = machine(&QUINE, cache);
+    ic.execute().unwrap();
+    ic.cycles()
+}
+
+/// Five fresh amplifiers in a feedback loop, as in aoc7 part 2.
+fn amplifiers(cache: bool) -> u64 {
+    feedback(&machine(&AMPLIFIER, cache), &[9,8,7,6,5])
+}
+
+/// Runs clones of `amplifier` in a feedback loop, as aoc7 does.
+fn feedback(amplifier: &IntComputer, phases: &[i64]) -> u64 {
+    let mut amps: Vec<IntComputer> = phases.iter().map(|phase| {
+        let mut ic = amplifier.clone();
+        ic.write(*phase);
+        ic
+    }).collect();
+    let mut signal = 0;
+    while amps.iter().any(|amp| *amp.state() != intcode::ExecutionState::Halted) {
+        for amp in amps.iter_mut() {
+            amp.write(signal);
+            amp.execute().unwrap();
+            signal = amp.read().unwrap();
+        }
+    }
+    amps.iter().map(|amp| amp.cycles()).sum()
+}
+
+/// Every phase permutation of aoc7 part 2 on the real puzzle input: 120
+/// fresh sets of five amplifiers.
+fn aoc7(cache: bool) -> u64 {
+    let program = load::file(AOC7_INPUT).unwrap_or_else(|e| panic!("{}: {}", AOC7_INPUT, e));
+    let amplifier = machine(&program, cache);
+    let mut phases = [5, 6, 7, 8, 9];
+    let mut cycles = 0;
+    for _ in 0..120 {
+        cycles += feedback(&amplifier, &phases);
+        next_permutation(&mut phases);
+    }
+    cycles
+}
+
+fn next_permutation(values: &mut [i64]) {
+    if let Some(i) = (0..values.len() - 1).rev().find(|&i| values[i] < values[i + 1]) {
+        let j = (i + 1..values.len()).rev().find(|&j| values[j] > values[i]).unwrap();
+        values.swap(i, j);
+        values[i + 1..].reverse();
+    }
+}
+
+fn countdown(cache: bool) -> u64 {
+    let mut ic = machine(&COUNTDOWN, cache);
+    ic.write(1_000_000);
+    ic.execute().unwrap();
+    ic.cycles()
+}
+
+fn run(bench: Bench, runs: usize, cache: bool) -> (Duration, u64) {
+    let start = Instant::now();
+    let mut cycles = 0;
+    for _ in 0..runs {
+        cycles = bench(cache);
+    }
+    (start.elapsed(), cycles)
+}
+
+/// Best of seven timings of `runs` calls to `bench` without and with the
+/// cache, alternating so both see the same machine load, and the
+/// instructions one call executes.
+fn time(bench: Bench, runs: usize) -> (Duration, Duration, u64) {
+    let (mut plain, mut cached, mut cycles) = (Duration::MAX, Duration::MAX, 0);
+    for _ in 0..7 {
+        let (time, count) = run(bench, runs, false);
+        plain = plain.min(time);
+        cycles = count;
+        cached = cached.min(run(bench, runs, true).0);
+    }
+    (plain, cached, cycles)
+}
+
+fn main() {
+    let cases: [(&str, Bench, usize); 4] = [
+        ("quine", quine, 20_000),
+        ("amplifiers", amplifiers, 20_000),
+        ("aoc7", aoc7, 20),
+        ("countdown", countdown, 10),
+    ];
+    println!("{:<11} {:>10} {:>10} {:>10} {:>8}", "program", "steps", "plain", "cached", "speedup");
+    for (name, bench, runs) in cases.iter() {
+        let (plain, cached, cycles) = time(*bench, *runs);
+        println!("{:<11} {:>10} {:>8}ms {:>8}ms {:>7.2}x", name, cycles * *runs as u64,
+            plain.as_millis(), cached.as_millis(), plain.as_secs_f64() / cached.as_secs_f64());
+    }
+    println!("The cache only helps long-running loops such as countdown; the other programs");
+    println!("run too few instructions per machine to make up for decoding memory.");
+}
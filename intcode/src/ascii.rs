@@ -0,0 +1,125 @@
+use crate::{ExecutionState, IntComputer, IntcodeError};
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsciiOutput {
+    /// A line of text, without its newline.
+    Line(String),
+    /// A value outside the ASCII range, e.g. a final answer.
+    Value(i64)
+}
+
+/// A character `send_line` cannot send, since it is outside ASCII.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NonAsciiChar(pub char);
+
+impl fmt::Display for NonAsciiChar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "non-ASCII character {:?}", self.0)
+    }
+}
+
+impl std::error::Error for NonAsciiChar {}
+
+/// Wraps an `IntComputer` that talks in ASCII: input is sent as whole
+/// lines and output is collected back into lines of text.
+pub struct AsciiComputer {
+    computer: IntComputer,
+    pending: String
+}
+
+impl AsciiComputer {
+    pub fn new(computer: IntComputer) -> AsciiComputer {
+        AsciiComputer { computer, pending: String::new() }
+    }
+
+    pub fn load(program: Vec<i64>) -> AsciiComputer {
+        AsciiComputer::new(IntComputer::load(program))
+    }
+
+    pub fn computer(&self) -> &IntComputer {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut IntComputer {
+        &mut self.computer
+    }
+
+    /// Queues `line` followed by a newline. Nothing is queued if the line
+    /// contains a character outside ASCII.
+    pub fn send_line(&mut self, line: &str) -> Result<(), NonAsciiChar> {
+        if let Some(c) = line.chars().find(|c| !c.is_ascii()) {
+            return Err(NonAsciiChar(c))
+        }
+        for c in line.chars().chain(Some('\n')) {
+            self.computer.write(c as i64);
+        }
+        Ok(())
+    }
+
+    pub fn execute(&mut self) -> Result<&ExecutionState, IntcodeError> {
+        self.computer.execute()
+    }
+
+    /// Drains the machine's output into complete lines and non-ASCII
+    /// values, in the order they were produced. Text before a value ends a
+    /// line of its own. Text after the last newline is kept back until the
+    /// line is finished or `take_pending` is called.
+    pub fn read_output(&mut self) -> Vec<AsciiOutput> {
+        let mut output = Vec::new();
+        while let Some(value) = self.computer.read() {
+            match value {
+                10 => output.push(AsciiOutput::Line(std::mem::take(&mut self.pending))),
+                0..=127 => self.pending.push(value as u8 as char),
+                _ => {
+                    if let Some(text) = self.take_pending() {
+                        output.push(AsciiOutput::Line(text));
+                    }
+                    output.push(AsciiOutput::Value(value));
+                }
+            }
+        }
+        output
+    }
+
+    /// Text output since the last newline, such as a prompt.
+    pub fn take_pending(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.pending))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo() {
+        let mut ascii = AsciiComputer::load(vec![3,7,4,7,1105,1,0,0]);
+        ascii.send_line("hello").unwrap();
+        assert_eq!(ascii.send_line("héllo"), Err(NonAsciiChar('é')));
+        ascii.send_line("world").unwrap();
+        assert_eq!(ascii.execute(), Ok(&ExecutionState::WaitingInput));
+        assert_eq!(ascii.read_output(), vec![
+            AsciiOutput::Line("hello".to_string()),
+            AsciiOutput::Line("world".to_string()),
+        ]);
+        assert_eq!(ascii.take_pending(), None);
+    }
+
+    #[test]
+    fn test_values_and_pending() {
+        let mut ascii = AsciiComputer::load(vec![104,72,104,10,104,61,104,1000,104,105,104,63,99]);
+        assert_eq!(ascii.execute(), Ok(&ExecutionState::Halted));
+        assert_eq!(ascii.read_output(), vec![
+            AsciiOutput::Line("H".to_string()),
+            AsciiOutput::Line("=".to_string()),
+            AsciiOutput::Value(1000),
+        ]);
+        assert_eq!(ascii.take_pending(), Some("i?".to_string()));
+    }
+}
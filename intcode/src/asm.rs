@@ -0,0 +1,295 @@
+use crate::instruction::{operation_by_mnemonic, Mode, Operation};
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    OperandCount { expected: usize, found: usize },
+    InvalidOperand(String),
+    ImmediateWrite,
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    AddressMismatch { expected: usize, actual: usize }
+}
+
+/// An assembly error, with the 1-based line number it was found on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic {:?}", m),
+            AsmErrorKind::OperandCount { expected, found } =>
+                write!(f, "expected {} operands, found {}", expected, found),
+            AsmErrorKind::InvalidOperand(o) => write!(f, "invalid operand {:?}", o),
+            AsmErrorKind::ImmediateWrite => write!(f, "write operand cannot be immediate"),
+            AsmErrorKind::InvalidLabel(l) => write!(f, "invalid label {:?}", l),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "label {:?} defined twice", l),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "undefined label {:?}", l),
+            AsmErrorKind::AddressMismatch { expected, actual } =>
+                write!(f, "expected address {}, but assembling at {}", expected, actual),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Debug)]
+enum Value {
+    Number(i64),
+    Label(String, i64)
+}
+
+#[derive(Debug)]
+struct Operand {
+    mode: Mode,
+    value: Value
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction(&'static Operation, Vec<Operand>),
+    Data(Vec<Value>)
+}
+
+/// Assembles source text into a program ready for `IntComputer::load`.
+///
+/// Each line holds optional labels (`name:`), address checks (`12:`, as
+/// printed by the disassembler), and an instruction or `data` directive.
+/// Operands are written `#n` (immediate), `[n]` (position) or `[rb+n]`
+/// (relative), where `n` is a number, a label, or `label+n`. Comments start
+/// with `;`.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |kind| AsmError { line, kind };
+        let mut text = text.split(';').next().unwrap().trim();
+
+        while let Some(colon) = text.find(':') {
+            let name = text[..colon].trim();
+            if let Ok(expected) = name.parse::<usize>() {
+                if expected != address {
+                    return Err(error(AsmErrorKind::AddressMismatch { expected, actual: address }))
+                }
+            } else if is_label(name) {
+                if labels.insert(name.to_string(), address).is_some() {
+                    return Err(error(AsmErrorKind::DuplicateLabel(name.to_string())))
+                }
+            } else {
+                return Err(error(AsmErrorKind::InvalidLabel(name.to_string())))
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(space) => (&text[..space], text[space..].trim()),
+            None => (text, "")
+        };
+        let item = if mnemonic == "data" {
+            let values = split_operands(rest).iter()
+                .map(|v| parse_value(v).ok_or_else(|| error(AsmErrorKind::InvalidOperand(v.to_string()))))
+                .collect::<Result<Vec<_>, _>>()?;
+            address += values.len();
+            Item::Data(values)
+        } else {
+            let operation = operation_by_mnemonic(mnemonic)
+                .ok_or_else(|| error(AsmErrorKind::UnknownMnemonic(mnemonic.to_string())))?;
+            let operands = parse_operands(operation, rest).map_err(error)?;
+            address += 1 + operands.len();
+            Item::Instruction(operation, operands)
+        };
+        items.push((line, item));
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, item) in items {
+        let resolve = |value: &Value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(name, offset) => labels.get(name)
+                .map(|address| *address as i64 + offset)
+                .ok_or_else(|| AsmError { line, kind: AsmErrorKind::UndefinedLabel(name.clone()) })
+        };
+        match item {
+            Item::Instruction(operation, operands) => {
+                let mut word = operation.opcode;
+                let mut scale = 100;
+                for operand in &operands {
+                    word += operand.mode.digit() * scale;
+                    scale *= 10;
+                }
+                program.push(word);
+                for operand in &operands {
+                    program.push(resolve(&operand.value)?);
+                }
+            },
+            Item::Data(values) => {
+                for value in &values {
+                    program.push(resolve(value)?);
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+fn parse_operands(operation: &Operation, text: &str) -> Result<Vec<Operand>, AsmErrorKind> {
+    let (inputs, target) = match text.find("->") {
+        Some(arrow) => (&text[..arrow], Some(text[arrow + 2..].trim())),
+        None => (text, None)
+    };
+    let mut texts = split_operands(inputs);
+    texts.extend(target);
+    if texts.len() != operation.arity() {
+        return Err(AsmErrorKind::OperandCount { expected: operation.arity(), found: texts.len() })
+    }
+    let mut operands = Vec::new();
+    for text in texts {
+        let operand = parse_operand(text).ok_or_else(|| AsmErrorKind::InvalidOperand(text.to_string()))?;
+        operands.push(operand);
+    }
+    if operation.writes && operands.last().unwrap().mode == Mode::Immediate {
+        return Err(AsmErrorKind::ImmediateWrite)
+    }
+    Ok(operands)
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new()
+    }
+    text.split(',').map(|t| t.trim()).collect()
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Some(Operand { mode: Mode::Immediate, value: parse_value(value)? })
+    }
+    let inner = text.strip_prefix('[')?.strip_suffix(']')?.trim();
+    if let Some(offset) = inner.strip_prefix("rb").map(|o| o.trim()) {
+        let value = match offset.strip_prefix('+') {
+            Some(positive) => Some(parse_value(positive)?),
+            None if offset.starts_with('-') => Some(parse_value(offset)?),
+            None if offset.is_empty() => Some(Value::Number(0)),
+            None => None
+        };
+        if let Some(value) = value {
+            return Some(Operand { mode: Mode::Relative, value })
+        }
+    }
+    Some(Operand { mode: Mode::Position, value: parse_value(inner)? })
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(n) = text.parse() {
+        return Some(Value::Number(n))
+    }
+    let (name, offset) = match text.rfind(['+', '-']) {
+        Some(sign) => {
+            let offset: i64 = text[sign + 1..].trim().parse().ok()?;
+            let offset = if &text[sign..sign + 1] == "-" { -offset } else { offset };
+            (text[..sign].trim(), offset)
+        },
+        None => (text, 0)
+    };
+    if is_label(name) {
+        Some(Value::Label(name.to_string(), offset))
+    } else {
+        None
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false
+    }
+    name != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::listing;
+    use crate::IntComputer;
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble("
+            ; doubles its input
+            start:  in -> [value]
+                    mul [value], #2 -> [value]
+                    out [value]
+                    halt
+            value:  data 0
+        ").unwrap();
+        assert_eq!(program, vec![3,9,1002,9,2,9,4,9,99,0]);
+        let mut ic = IntComputer::load(program);
+        ic.write(21);
+        ic.execute().unwrap();
+        assert_eq!(ic.read(), Some(42));
+    }
+
+    #[test]
+    fn test_labels_and_relative() {
+        let program = assemble("
+                    arb #table
+            loop:   out [rb+0]
+                    arb #1
+                    jf [rb], #end
+                    jt #1, #loop
+            end:    halt
+            table:  data 7, 8, -9, 0
+                    data end, table+1
+        ").unwrap();
+        assert_eq!(program, vec![109,13,204,0,109,1,1206,0,12,1105,1,2,99,7,8,-9,0,12,14]);
+        let mut ic = IntComputer::load(program);
+        ic.execute().unwrap();
+        assert_eq!((ic.read(), ic.read(), ic.read(), ic.read()), (Some(7), Some(8), Some(-9), None));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let programs = vec![
+            vec![1,9,10,3,2,3,11,0,99,30,40,50],
+            vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9],
+            vec![3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
+                 1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
+                 999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99],
+            vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99],
+            // Mode digits past an operation's parameters are ignored.
+            vec![10104,7,21109,3,1102,2,3,9,99,0],
+        ];
+        for program in programs {
+            assert_eq!(assemble(&listing(&program)), Ok(program));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source).unwrap_err();
+        assert_eq!(error("halt\nfoo [1]"), AsmError { line: 2, kind: AsmErrorKind::UnknownMnemonic("foo".to_string()) });
+        assert_eq!(error("add [1], [2]").kind, AsmErrorKind::OperandCount { expected: 3, found: 2 });
+        assert_eq!(error("add [1], [2] -> #3").kind, AsmErrorKind::ImmediateWrite);
+        assert_eq!(error("out 5").kind, AsmErrorKind::InvalidOperand("5".to_string()));
+        assert_eq!(error("\n\njt #1, #nowhere"), AsmError { line: 3, kind: AsmErrorKind::UndefinedLabel("nowhere".to_string()) });
+        assert_eq!(error("a: halt\na: halt").kind, AsmErrorKind::DuplicateLabel("a".to_string()));
+        assert_eq!(error("halt\n    5: halt").kind, AsmErrorKind::AddressMismatch { expected: 5, actual: 1 });
+    }
+}
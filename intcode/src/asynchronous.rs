@@ -0,0 +1,118 @@
+use crate::{ExecutionState, IntComputer, IntcodeError};
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{Future, Stream, StreamExt};
+
+/// Runs `computer` to completion, awaiting `input` whenever the program
+/// asks for a value and sending every output value to `output`.
+///
+/// Resolves when the machine halts, fails, or wants input after `input`
+/// has ended (reported as `WaitingInput`). Values sent after `output`'s
+/// receiver has been dropped are discarded.
+pub async fn run<S>(mut computer: IntComputer, mut input: S, output: UnboundedSender<i64>)
+    -> Result<ExecutionState, IntcodeError>
+    where S: Stream<Item = i64> + Unpin {
+    loop {
+        let state = computer.execute()?.clone();
+        while let Some(value) = computer.read() {
+            let _ = output.unbounded_send(value);
+        }
+        match state {
+            ExecutionState::WaitingInput => match input.next().await {
+                Some(value) => computer.write(value),
+                None => return Ok(state)
+            },
+            _ => return Ok(state)
+        }
+    }
+}
+
+/// Channels for talking to a machine started with `machine`.
+pub struct AsyncMachine {
+    pub input: UnboundedSender<i64>,
+    pub output: UnboundedReceiver<i64>
+}
+
+/// Wraps `computer` in a future fed by an unbounded channel. The returned
+/// future does nothing until polled, e.g. by spawning it on a local
+/// executor; its output is exposed as a `Stream`.
+pub fn machine(computer: IntComputer)
+    -> (AsyncMachine, impl Future<Output = Result<ExecutionState, IntcodeError>>) {
+    let (input, machine_input) = unbounded();
+    let (machine_output, output) = unbounded();
+    (AsyncMachine { input, output }, run(computer, machine_input, machine_output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::{block_on, LocalPool};
+    use futures::task::LocalSpawnExt;
+    use futures::stream;
+
+    #[test]
+    fn test_machine() {
+        let (machine, future) = machine(IntComputer::load(vec![3,9,8,9,10,9,4,9,99,-1,8]));
+        machine.input.unbounded_send(8).unwrap();
+        assert_eq!(block_on(future), Ok(ExecutionState::Halted));
+        assert_eq!(block_on(machine.output.collect::<Vec<_>>()), vec![1]);
+    }
+
+    #[test]
+    fn test_input_ended() {
+        let (sender, receiver) = unbounded();
+        let result = block_on(run(IntComputer::load(vec![3,7,4,7,1105,1,0,0]), stream::iter(vec![4, 5]), sender));
+        assert_eq!(result, Ok(ExecutionState::WaitingInput));
+        assert_eq!(block_on(receiver.collect::<Vec<_>>()), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_many_machines() {
+        let increment = IntComputer::load(vec![3,9,1001,9,1,9,4,9,99,0]);
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+        let (first, mut previous) = unbounded();
+        for _ in 0..500 {
+            let (sender, receiver) = unbounded();
+            let machine = increment.clone();
+            spawner.spawn_local(async move {
+                run(machine, previous, sender).await.unwrap();
+            }).unwrap();
+            previous = receiver;
+        }
+        first.unbounded_send(0).unwrap();
+        let last = pool.run_until(previous.next());
+        assert_eq!(last, Some(500));
+    }
+
+    #[test]
+    fn test_feedback_ring() {
+        let program = IntComputer::load(vec![3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,
+            -5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,
+            53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10]);
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+
+        let (first, mut previous) = unbounded();
+        for phase in [9,7,8,5,6] {
+            let (sender, receiver) = unbounded();
+            let mut amplifier = program.clone();
+            amplifier.write(phase);
+            spawner.spawn_local(async move {
+                run(amplifier, previous, sender).await.unwrap();
+            }).unwrap();
+            previous = receiver;
+        }
+        first.unbounded_send(0).unwrap();
+        let last = pool.run_until(async move {
+            let mut last = None;
+            while let Some(value) = previous.next().await {
+                let _ = first.unbounded_send(value);
+                last = Some(value);
+            }
+            last
+        });
+        assert_eq!(last, Some(18216));
+    }
+}
@@ -0,0 +1,53 @@
+use intcode::ascii::{AsciiComputer, AsciiOutput};
+use intcode::ExecutionState;
+use intcode::load;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: ascii <program>");
+            process::exit(2)
+        }
+    };
+    let program = load::file(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1)
+    });
+
+    let mut ascii = AsciiComputer::load(program);
+    let stdin = io::stdin();
+    loop {
+        let state = ascii.execute().cloned();
+        for output in ascii.read_output() {
+            match output {
+                AsciiOutput::Line(line) => println!("{}", line),
+                AsciiOutput::Value(value) => println!("{}", value)
+            }
+        }
+        if let Some(prompt) = ascii.take_pending() {
+            print!("{}", prompt);
+        }
+        io::stdout().flush().unwrap();
+        match state {
+            Ok(ExecutionState::WaitingInput) => {
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line).unwrap() == 0 {
+                    break
+                }
+                if let Err(error) = ascii.send_line(line.trim_end_matches(&['\r', '\n'][..])) {
+                    eprintln!("{}", error);
+                }
+            },
+            Ok(_) => break,
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1)
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use intcode::control_flow::ControlFlowGraph;
+use intcode::decompile::decompile;
+use intcode::disasm;
+use intcode::load;
+
+use std::env;
+use std::process;
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mode = match args.first().map(|arg| arg.as_str()) {
+        Some(flag @ "--dot") | Some(flag @ "--decompile") => flag.to_string(),
+        _ => String::new()
+    };
+    if !mode.is_empty() {
+        args.remove(0);
+    }
+    let path = match args.pop() {
+        Some(path) if args.is_empty() => path,
+        _ => {
+            eprintln!("usage: disasm [--dot | --decompile] <program>");
+            process::exit(2)
+        }
+    };
+    let program = load::file(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1)
+    });
+    match mode.as_str() {
+        "--dot" => print!("{}", ControlFlowGraph::new(&program).to_dot()),
+        "--decompile" => print!("{}", decompile(&program)),
+        _ => print!("{}", disasm::listing(&program))
+    }
+}
@@ -0,0 +1,42 @@
+use intcode::debugger::Debugger;
+use intcode::IntComputer;
+use intcode::load;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: intdbg <program>");
+            process::exit(2)
+        }
+    };
+    let program = load::file(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1)
+    });
+
+    let mut debugger = Debugger::new(IntComputer::load(program));
+    println!("{}", debugger.command("regs"));
+    let stdin = io::stdin();
+    loop {
+        print!("(intdbg) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break
+        }
+        match line.trim() {
+            "q" | "quit" => break,
+            command => {
+                let response = debugger.command(command);
+                if !response.is_empty() {
+                    println!("{}", response);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::instruction::{operation, split_opcode, Mode};
+use crate::io::{Input, Output};
+use crate::{ErrorKind, ExecutionState, IntComputer, IntcodeError};
+
+use std::sync::Arc;
+
+/// A valid instruction, decoded once. Position parameters are known to be
+/// valid addresses, and written parameters are never immediate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Decoded {
+    opcode: u8,
+    size: u8,
+    modes: [Mode; 3],
+    parameters: [i64; 3]
+}
+
+impl Decoded {
+    /// Decodes the instruction at `address`. Anything the plain interpreter
+    /// would fail on, or that reads past the end of memory, is not cached.
+    fn new(memory: &[i64], address: usize) -> Option<Decoded> {
+        let (opcode, mode1, mode2, mode3) = split_opcode(*memory.get(address)?);
+        let operation = operation(opcode)?;
+        let size = operation.arity() + 1;
+        let values = memory.get(address + 1..address + size)?;
+        let mut modes = [Mode::Immediate; 3];
+        let mut parameters = [0; 3];
+        for (index, (&value, &digit)) in values.iter().zip(&[mode1, mode2, mode3]).enumerate() {
+            let mode = Mode::from_digit(digit)?;
+            let write = operation.writes && index == operation.reads;
+            if (mode == Mode::Immediate && write) || (mode == Mode::Position && value < 0) {
+                return None
+            }
+            modes[index] = mode;
+            parameters[index] = value;
+        }
+        Some(Decoded { opcode: opcode as u8, size: size as u8, modes, parameters })
+    }
+}
+
+/// Memory as it was decoded, shared between clones.
+#[derive(Debug)]
+struct Table {
+    /// The instruction at each address, if the word there decodes.
+    decoded: Vec<Option<Decoded>>,
+    /// For each address, bit `i` is set when the instruction `i` words
+    /// before it covers it.
+    covers: Vec<u8>
+}
+
+/// Memory decoded at every address when the cache was turned on. Clones
+/// of a machine share the decoded table, and each keeps its own record of
+/// the entries its writes have made stale, which then run uncached.
+#[derive(Debug, Clone)]
+pub(crate) struct DecodeCache {
+    table: Arc<Table>,
+    /// One bit per table entry, set once a write lands on a word the entry
+    /// was decoded from.
+    stale: Vec<u64>
+}
+
+impl DecodeCache {
+    pub fn new(memory: &[i64]) -> DecodeCache {
+        let decoded: Vec<Option<Decoded>> = (0..memory.len())
+            .map(|address| Decoded::new(memory, address))
+            .collect();
+        let mut covers = vec![0; memory.len()];
+        for (start, entry) in decoded.iter().enumerate() {
+            if let Some(entry) = entry {
+                for offset in 0..entry.size as usize {
+                    covers[start + offset] |= 1 << offset;
+                }
+            }
+        }
+        DecodeCache { table: Arc::new(Table { decoded, covers }), stale: vec![0; memory.len().div_ceil(64)] }
+    }
+
+    fn entry(&self, address: usize) -> Option<Decoded> {
+        let decoded = (*self.table.decoded.get(address)?)?;
+        if self.stale[address / 64] & 1 << (address % 64) != 0 {
+            return None
+        }
+        Some(decoded)
+    }
+
+    /// Marks every entry that covers `address` as stale.
+    pub fn invalidate(&mut self, address: usize) {
+        let covers = match self.table.covers.get(address) {
+            Some(&covers) if covers != 0 => covers,
+            _ => return
+        };
+        for offset in 0..4 {
+            if covers & 1 << offset != 0 {
+                let start = address - offset;
+                self.stale[start / 64] |= 1 << (start % 64);
+            }
+        }
+    }
+}
+
+impl<I: Input, O: Output> IntComputer<I, O> {
+    /// Runs from the decode cache until the machine stops running or the
+    /// cycle counter reaches `cycles`. Instructions that cannot be decoded
+    /// are left to `step`, which reports any error.
+    pub(crate) fn run_cached(&mut self, cycles: u64) -> Result<(), IntcodeError> {
+        while self.cycles < cycles {
+            let decoded = match self.cached_instruction() {
+                Some(decoded) => decoded,
+                None => {
+                    self.step()?;
+                    match self.execution_state {
+                        ExecutionState::Running => {
+                            self.cycles += 1;
+                            continue
+                        },
+                        ExecutionState::Halted => self.cycles += 1,
+                        _ => ()
+                    }
+                    return Ok(())
+                }
+            };
+            let [mode1, mode2, mode3] = decoded.modes;
+            let [first, second, third] = decoded.parameters;
+            match decoded.opcode {
+                1 => {
+                    let (a, b) = (self.operand(mode1, first)?, self.operand(mode2, second)?);
+                    let target = self.target(mode3, third)?;
+                    let sum = a.checked_add(b).ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                    self.store_unhooked(target, sum)?;
+                },
+                2 => {
+                    let (a, b) = (self.operand(mode1, first)?, self.operand(mode2, second)?);
+                    let target = self.target(mode3, third)?;
+                    let product = a.checked_mul(b).ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                    self.store_unhooked(target, product)?;
+                },
+                3 => {
+                    let target = self.target(mode1, first)?;
+                    match self.input.next_input() {
+                        Some(value) => self.store_unhooked(target, value)?,
+                        None => {
+                            self.execution_state = ExecutionState::WaitingInput;
+                            return Ok(())
+                        }
+                    }
+                },
+                4 => {
+                    let value = self.operand(mode1, first)?;
+                    self.output.send_output(value);
+                },
+                5 | 6 => {
+                    let (condition, target) = (self.operand(mode1, first)?, self.operand(mode2, second)?);
+                    if (condition != 0) == (decoded.opcode == 5) {
+                        self.position = self.address(target)?;
+                        self.cycles += 1;
+                        continue
+                    }
+                },
+                7 | 8 => {
+                    let (a, b) = (self.operand(mode1, first)?, self.operand(mode2, second)?);
+                    let target = self.target(mode3, third)?;
+                    let result = if decoded.opcode == 7 { a < b } else { a == b };
+                    self.store_unhooked(target, result as i64)?;
+                },
+                9 => {
+                    let offset = self.operand(mode1, first)?;
+                    self.relative_base = self.relative_base.checked_add(offset)
+                        .ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                },
+                _ => {
+                    self.execution_state = ExecutionState::Halted;
+                    self.cycles += 1;
+                    return Ok(())
+                }
+            }
+            self.position += decoded.size as usize;
+            self.cycles += 1;
+        }
+        Ok(())
+    }
+
+    fn cached_instruction(&self) -> Option<Decoded> {
+        self.cache.as_ref()?.entry(self.position)
+    }
+
+    /// `store` without recording `last_write`, which only hooks look at.
+    fn store_unhooked(&mut self, address: usize, value: i64) -> Result<(), IntcodeError> {
+        match self.memory_limit {
+            Some(limit) if address >= limit => self.store(address, value),
+            _ => self.set(address, value)
+        }
+    }
+
+    fn operand(&self, mode: Mode, parameter: i64) -> Result<i64, IntcodeError> {
+        match mode {
+            Mode::Immediate => Ok(parameter),
+            _ => Ok(self.fetch(self.target(mode, parameter)?))
+        }
+    }
+
+    fn target(&self, mode: Mode, parameter: i64) -> Result<usize, IntcodeError> {
+        match mode {
+            Mode::Relative => {
+                let address = self.relative_base.checked_add(parameter)
+                    .ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                self.address(address)
+            },
+            _ => Ok(parameter as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(program: &[i64], input: &[i64], cache: bool) -> (Result<ExecutionState, IntcodeError>, IntComputer) {
+        let mut ic = IntComputer::load(program.to_vec());
+        ic.set_decode_cache(cache);
+        for value in input {
+            ic.write(*value);
+        }
+        let result = ic.execute_for(100_000).cloned();
+        (result, ic)
+    }
+
+    #[test]
+    fn test_matches_plain() {
+        let programs: [(&[i64], &[i64]); 5] = [
+            (&[109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99], &[]),
+            (&[3,12,1001,12,-1,12,1005,12,2,104,0,99,0], &[1000]),
+            // Loops until the add overflows.
+            (&[1,0,0,0,1105,1,0], &[]),
+            (&[3,0,4,0,1105,1,0], &[1, 2, 3]),
+            (&[1105,1,0], &[]),
+        ];
+        for (program, input) in programs.iter() {
+            let (plain, plain_ic) = run(program, input, false);
+            let (cached, cached_ic) = run(program, input, true);
+            assert_eq!(cached, plain);
+            assert_eq!(cached_ic.snapshot(), plain_ic.snapshot());
+            assert_eq!(cached_ic.cycles(), plain_ic.cycles());
+        }
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // The second pass must see the patched operand of the first `add`.
+        let program = [1001,20,7,20,1101,0,100,2,1008,20,107,21,1006,21,0,4,20,99];
+        let (result, mut ic) = run(&program, &[], true);
+        assert_eq!(result, Ok(ExecutionState::Halted));
+        assert_eq!(ic.read(), Some(107));
+    }
+
+    #[test]
+    fn test_clones() {
+        // A clone patching its code must not affect the machine it was
+        // cloned from, which shares its decoded memory.
+        let program = [1001,20,7,20,1101,0,100,2,1008,20,107,21,1006,21,0,4,20,99,0,0,0,0];
+        let mut template = IntComputer::load(program.to_vec());
+        template.set_decode_cache(true);
+        let mut first = template.clone();
+        first.execute().unwrap();
+        template.execute().unwrap();
+        assert_eq!(first.snapshot(), template.snapshot());
+        assert_eq!(template.read(), Some(107));
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let mut cache = DecodeCache::new(&[1,0,0,0,2,0,0,0,99]);
+        assert_eq!(cache.entry(1), None);
+        cache.invalidate(3);
+        assert_eq!(cache.entry(0), None);
+        assert!(cache.entry(4).is_some());
+        let clone = cache.clone();
+        cache.invalidate(100);
+        cache.invalidate(7);
+        cache.invalidate(8);
+        assert_eq!((cache.entry(4), cache.entry(8)), (None, None));
+        assert!(Arc::ptr_eq(&cache.table, &clone.table));
+        assert!(clone.entry(4).is_some());
+        assert_eq!(clone.entry(0), None);
+    }
+}
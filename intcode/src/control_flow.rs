@@ -0,0 +1,225 @@
+use crate::disasm::instruction_starts;
+use crate::instruction::{Instruction, Parameter};
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// A run of instructions with a single entry at `start` and no jumps
+/// before its last instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<Instruction>
+}
+
+impl Block {
+    /// The address just past the block's last instruction.
+    pub fn end(&self) -> usize {
+        self.instructions.last().map_or(self.start, |i| i.next())
+    }
+
+    pub fn last(&self) -> &Instruction {
+        self.instructions.last().unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    /// The jump at the end of the block is taken.
+    Jump,
+    /// Execution continues with the next instruction.
+    FallThrough
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Block(usize),
+    /// A jump whose destination is read from memory at run time.
+    Unresolved(Parameter),
+    /// An address where no instruction starts: past the end of the
+    /// program, inside another instruction, or on a word that does not
+    /// decode.
+    Invalid(usize)
+}
+
+/// An edge from the block starting at `from`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: Target,
+    pub kind: EdgeKind
+}
+
+/// The basic blocks of the code reachable from address 0 and the edges
+/// between them. Only jumps with immediate targets are followed, so code
+/// reached only through an unresolved edge is missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &[i64]) -> ControlFlowGraph {
+        let starts = instruction_starts(program);
+        let instructions: Vec<Instruction> = (0..program.len())
+            .filter(|address| starts[*address])
+            .map(|address| Instruction::decode(program, address).unwrap())
+            .collect();
+        let mut leaders: BTreeSet<usize> = instructions.iter()
+            .filter(|i| i.branches().0)
+            .filter_map(|i| i.jump_target())
+            .collect();
+        leaders.insert(0);
+
+        let mut blocks: Vec<Block> = Vec::new();
+        for instruction in instructions {
+            match blocks.last_mut() {
+                Some(block) if block.end() == instruction.address
+                    && !leaders.contains(&instruction.address)
+                    && !ends_block(block.last()) => block.instructions.push(instruction),
+                _ => blocks.push(Block { start: instruction.address, instructions: vec![instruction] })
+            }
+        }
+
+        let target = |address: usize| if starts.get(address) == Some(&true) {
+            Target::Block(address)
+        } else {
+            Target::Invalid(address)
+        };
+        let mut edges = Vec::new();
+        for block in &blocks {
+            let last = block.last();
+            let from = block.start;
+            let (taken, falls) = last.branches();
+            if taken {
+                let to = match last.jump_target() {
+                    Some(address) => target(address),
+                    None => Target::Unresolved(last.parameters[1])
+                };
+                edges.push(Edge { from, to, kind: EdgeKind::Jump });
+            }
+            if falls {
+                edges.push(Edge { from, to: target(last.next()), kind: EdgeKind::FallThrough });
+            }
+        }
+        ControlFlowGraph { blocks, edges }
+    }
+
+    /// The block containing `address`, if it is code.
+    pub fn block_at(&self, address: usize) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.start <= address && address < b.end())
+    }
+
+    /// Jumps whose target could not be resolved statically.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(|e| matches!(e.to, Target::Unresolved(_)))
+    }
+
+    /// Edges to an address where no instruction starts.
+    pub fn invalid(&self) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(|e| matches!(e.to, Target::Invalid(_)))
+    }
+
+    /// Renders the graph in Graphviz DOT format. Fall-through edges are
+    /// dashed, unresolved jumps lead to a `?` node labelled with the operand
+    /// the target is read from, and invalid targets to a red node labelled
+    /// with their address.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph program {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in &self.blocks {
+            let mut label = String::new();
+            for instruction in &block.instructions {
+                write!(label, "{}: {}\\l", instruction.address, instruction).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+        let mut invalid = BTreeSet::new();
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Jump => "",
+                EdgeKind::FallThrough => " [style=dashed]"
+            };
+            match edge.to {
+                Target::Block(to) => writeln!(dot, "    b{} -> b{}{};", edge.from, to, style).unwrap(),
+                Target::Unresolved(parameter) => {
+                    writeln!(dot, "    u{} [label=\"?\", shape=circle, color=red];", edge.from).unwrap();
+                    writeln!(dot, "    b{} -> u{} [label=\"{}\", color=red];", edge.from, edge.from, parameter).unwrap();
+                },
+                Target::Invalid(to) => {
+                    if invalid.insert(to) {
+                        writeln!(dot, "    x{} [label=\"{}\", shape=octagon, color=red];", to, to).unwrap();
+                    }
+                    writeln!(dot, "    b{} -> x{}{};", edge.from, to, style).unwrap();
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn ends_block(instruction: &Instruction) -> bool {
+    instruction.operation.is_jump() || instruction.operation.opcode == 99
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Mode;
+
+    #[test]
+    fn test_blocks() {
+        // Doubles its input until it reaches 100, then prints it.
+        let program = vec![3,19,1007,19,100,20,1006,20,16,1002,19,2,19,1105,1,2,4,19,99,0,0];
+        let graph = ControlFlowGraph::new(&program);
+        let starts: Vec<usize> = graph.blocks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 2, 9, 16]);
+        assert_eq!(graph.edges, vec![
+            Edge { from: 0, to: Target::Block(2), kind: EdgeKind::FallThrough },
+            Edge { from: 2, to: Target::Block(16), kind: EdgeKind::Jump },
+            Edge { from: 2, to: Target::Block(9), kind: EdgeKind::FallThrough },
+            Edge { from: 9, to: Target::Block(2), kind: EdgeKind::Jump },
+        ]);
+        assert_eq!(graph.block_at(11).map(|b| b.start), Some(9));
+        assert_eq!(graph.block_at(19), None);
+        assert_eq!(graph.unresolved().count(), 0);
+    }
+
+    #[test]
+    fn test_unresolved_dot() {
+        let graph = ControlFlowGraph::new(&[3,6,6,7,6,99,0,0]);
+        let unresolved: Vec<&Edge> = graph.unresolved().collect();
+        assert_eq!(unresolved, vec![&Edge {
+            from: 0,
+            to: Target::Unresolved(Parameter { mode: Mode::Position, value: 6 }),
+            kind: EdgeKind::Jump
+        }]);
+        assert_eq!(graph.to_dot(), concat!(
+            "digraph program {\n",
+            "    node [shape=box, fontname=\"monospace\"];\n",
+            "    b0 [label=\"0: in -> [6]\\l2: jf [7], [6]\\l\"];\n",
+            "    b5 [label=\"5: halt\\l\"];\n",
+            "    u0 [label=\"?\", shape=circle, color=red];\n",
+            "    b0 -> u0 [label=\"[6]\", color=red];\n",
+            "    b0 -> b5 [style=dashed];\n",
+            "}\n"));
+    }
+
+    #[test]
+    fn test_invalid_targets() {
+        // Jumps past the end, and into the middle of its own first
+        // instruction, which then falls off the end of the program.
+        let graph = ControlFlowGraph::new(&[1006,7,100,1105,1,1,1101,0]);
+        assert_eq!(graph.edges, vec![
+            Edge { from: 0, to: Target::Invalid(100), kind: EdgeKind::Jump },
+            Edge { from: 0, to: Target::Block(3), kind: EdgeKind::FallThrough },
+            Edge { from: 3, to: Target::Invalid(1), kind: EdgeKind::Jump },
+        ]);
+        assert_eq!(graph.invalid().count(), 2);
+        assert!(graph.to_dot().contains("    x100 [label=\"100\", shape=octagon, color=red];\n    b0 -> x100;\n"));
+
+        let graph = ControlFlowGraph::new(&[1101,1,1,5,1,0]);
+        assert_eq!(graph.edges, vec![Edge { from: 0, to: Target::Invalid(4), kind: EdgeKind::FallThrough }]);
+    }
+}
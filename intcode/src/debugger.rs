@@ -0,0 +1,339 @@
+use crate::instruction::{operation, operation_by_mnemonic, Instruction};
+use crate::watch::{Access, WatchAction, WatchHit, Watchpoint};
+use crate::{ExecutionState, IntComputer, IntcodeError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    Address(usize),
+    Opcode(i64)
+}
+
+/// Why `Debugger::resume` stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    Breakpoint(Breakpoint),
+    Watchpoint(WatchHit),
+    State(ExecutionState)
+}
+
+/// Most cells or instructions `mem` and `list` show at once.
+const MAX_LINES: usize = 1000;
+
+pub struct Debugger {
+    computer: IntComputer,
+    breakpoints: Vec<Breakpoint>
+}
+
+const HELP: &str = "\
+step [n]            execute n instructions (s)
+continue            run until a breakpoint, input wait or halt (c)
+break <addr>        break before executing address (b)
+break op <op>       break before any instruction with opcode or mnemonic
+delete <addr|op..>  remove a breakpoint (d)
+breakpoints         list breakpoints
+watch <a[-b]> [how] pause when memory is read (r), written (w, default)
+                    or set to a value (=v)
+unwatch             remove all watchpoints
+list [addr] [n]     disassemble n instructions from addr (l)
+mem <addr> [n]      show n memory cells (x)
+poke <addr> <value> write a memory cell
+input <values..>    queue input values (i)
+output              drain queued output (o)
+regs                show instruction pointer, relative base and state (r)
+quit                leave the debugger (q)";
+
+impl Debugger {
+    pub fn new(computer: IntComputer) -> Debugger {
+        Debugger { computer, breakpoints: Vec::new() }
+    }
+
+    pub fn computer(&self) -> &IntComputer {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut IntComputer {
+        &mut self.computer
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|b| *b != breakpoint);
+        count != self.breakpoints.len()
+    }
+
+    /// The breakpoint that matches the instruction about to execute, if any.
+    pub fn breakpoint_hit(&self) -> Option<Breakpoint> {
+        let position = self.computer.position();
+        let opcode = self.computer.value(position) % 100;
+        self.breakpoints.iter().copied().find(|b| match b {
+            Breakpoint::Address(address) => *address == position,
+            Breakpoint::Opcode(op) => *op == opcode
+        })
+    }
+
+    pub fn step(&mut self) -> Result<&ExecutionState, IntcodeError> {
+        self.computer.execute_step()
+    }
+
+    /// Executes at least one instruction, then keeps going until a
+    /// breakpoint is reached or the machine stops running.
+    pub fn resume(&mut self) -> Result<Stop, IntcodeError> {
+        loop {
+            match self.computer.execute_step()? {
+                ExecutionState::Running => (),
+                ExecutionState::Paused => match self.computer.watch_hit() {
+                    Some(hit) => return Ok(Stop::Watchpoint(*hit)),
+                    None => return Ok(Stop::State(ExecutionState::Paused))
+                },
+                state => return Ok(Stop::State(state.clone()))
+            }
+            if let Some(breakpoint) = self.breakpoint_hit() {
+                return Ok(Stop::Breakpoint(breakpoint))
+            }
+        }
+    }
+
+    /// Runs one line of debugger input and returns the text to show.
+    pub fn command(&mut self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(String::new()),
+            ["s"] | ["step"] => self.command_step(1),
+            ["s", n] | ["step", n] => parse(n).and_then(|n| self.command_step(n)),
+            ["c"] | ["continue"] => self.command_continue(),
+            ["b", "op", op] | ["break", "op", op] => parse_opcode(op).map(|op| {
+                self.add_breakpoint(Breakpoint::Opcode(op));
+                format!("breakpoint on opcode {}", op)
+            }),
+            ["b", address] | ["break", address] => parse(address).map(|address| {
+                self.add_breakpoint(Breakpoint::Address(address));
+                format!("breakpoint at {}", address)
+            }),
+            ["d", "op", op] | ["delete", "op", op] => parse_opcode(op)
+                .and_then(|op| self.command_delete(Breakpoint::Opcode(op))),
+            ["d", address] | ["delete", address] => parse(address)
+                .and_then(|address| self.command_delete(Breakpoint::Address(address))),
+            ["breakpoints"] => Ok(self.command_breakpoints()),
+            ["watch", range] => self.command_watch(range, "w"),
+            ["watch", range, access] => self.command_watch(range, access),
+            ["unwatch"] => {
+                self.computer.clear_watchpoints();
+                Ok("watchpoints cleared".to_string())
+            },
+            ["l"] | ["list"] => self.list(self.computer.position(), 5),
+            ["l", address] | ["list", address] => parse(address).and_then(|a| self.list(a, 5)),
+            ["l", address, n] | ["list", address, n] =>
+                parse(address).and_then(|a| parse(n).and_then(|n| self.list(a, n))),
+            ["x", address] | ["mem", address] => parse(address).and_then(|a| self.memory(a, 1)),
+            ["x", address, n] | ["mem", address, n] =>
+                parse(address).and_then(|a| parse(n).and_then(|n| self.memory(a, n))),
+            ["poke", address, value] => parse(address).and_then(|address| parse(value).and_then(|value| {
+                self.computer.set(address, value).map_err(|e| e.kind.to_string())?;
+                self.memory(address, 1)
+            })),
+            ["i", values @ ..] | ["input", values @ ..] => self.command_input(values),
+            ["o"] | ["output"] => Ok(self.command_output()),
+            ["r"] | ["regs"] => Ok(self.registers()),
+            ["h"] | ["help"] => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command {:?}, try help", line.trim()))
+        };
+        result.unwrap_or_else(|message| format!("error: {}", message))
+    }
+
+    fn command_step(&mut self, count: usize) -> Result<String, String> {
+        for _ in 0..count {
+            match self.step().map_err(|e| e.to_string())? {
+                ExecutionState::Running => (),
+                _ => break
+            }
+        }
+        Ok(self.registers())
+    }
+
+    fn command_continue(&mut self) -> Result<String, String> {
+        let stop = match self.resume().map_err(|e| e.to_string())? {
+            Stop::Breakpoint(Breakpoint::Address(address)) => format!("breakpoint at {}", address),
+            Stop::Breakpoint(Breakpoint::Opcode(op)) => format!("breakpoint on opcode {}", op),
+            Stop::Watchpoint(hit) => format!("watchpoint: {}", hit),
+            Stop::State(state) => format!("{:?}", state)
+        };
+        Ok(format!("{}\n{}", stop, self.registers()))
+    }
+
+    fn command_delete(&mut self, breakpoint: Breakpoint) -> Result<String, String> {
+        if self.remove_breakpoint(breakpoint) {
+            Ok("deleted".to_string())
+        } else {
+            Err("no such breakpoint".to_string())
+        }
+    }
+
+    fn command_watch(&mut self, range: &str, access: &str) -> Result<String, String> {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(range)?, parse(range)?)
+        };
+        let access = match access {
+            "r" => Access::Read,
+            "w" => Access::Write,
+            _ => match access.strip_prefix('=') {
+                Some(value) => Access::WriteValue(parse(value)?),
+                None => return Err(format!("invalid access {:?}, use r, w or =value", access))
+            }
+        };
+        self.computer.add_watchpoint(Watchpoint::new(start..=end, access), WatchAction::Pause);
+        Ok(format!("watching {}..={}", start, end))
+    }
+
+    fn command_breakpoints(&self) -> String {
+        let lines: Vec<String> = self.breakpoints.iter().map(|b| match b {
+            Breakpoint::Address(address) => format!("address {}", address),
+            Breakpoint::Opcode(op) => format!("opcode {}", op)
+        }).collect();
+        lines.join("\n")
+    }
+
+    fn command_input(&mut self, values: &[&str]) -> Result<String, String> {
+        for value in values {
+            self.computer.write(parse(value)?);
+        }
+        Ok(format!("queued {} values", values.len()))
+    }
+
+    fn command_output(&mut self) -> String {
+        let mut values = Vec::new();
+        while let Some(value) = self.computer.read() {
+            values.push(value.to_string());
+        }
+        values.join(", ")
+    }
+
+    fn registers(&self) -> String {
+        let instruction = self.list(self.computer.position(), 1).unwrap_or_default();
+        format!("ip={} rb={} state={:?}\n{}",
+            self.computer.position(), self.computer.relative_base(),
+            self.computer.state(), instruction)
+    }
+
+    fn list(&self, mut address: usize, count: usize) -> Result<String, String> {
+        check_range(address, count)?;
+        let memory = self.computer.memory();
+        let mut lines = Vec::new();
+        for _ in 0..count {
+            match Instruction::decode(memory, address) {
+                Some(instruction) => {
+                    lines.push(format!("{:>5}: {}", address, instruction));
+                    address = instruction.next();
+                },
+                None => {
+                    lines.push(format!("{:>5}: data {}", address, self.computer.value(address)));
+                    address += 1;
+                }
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn memory(&self, address: usize, count: usize) -> Result<String, String> {
+        check_range(address, count)?;
+        let values: Vec<String> = (address..address + count)
+            .map(|a| self.computer.value(a).to_string())
+            .collect();
+        Ok(format!("{:>5}: {}", address, values.join(", ")))
+    }
+}
+
+/// Rejects showing more than `MAX_LINES` items, or items whose addresses
+/// would not fit in a `usize`. An instruction spans at most four cells.
+fn check_range(address: usize, count: usize) -> Result<(), String> {
+    if count > MAX_LINES {
+        return Err(format!("at most {} at a time", MAX_LINES))
+    }
+    match address.checked_add(count * 4) {
+        Some(_) => Ok(()),
+        None => Err(format!("address {} out of range", address))
+    }
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("invalid number {:?}", text))
+}
+
+fn parse_opcode(text: &str) -> Result<i64, String> {
+    let op = operation_by_mnemonic(text)
+        .or_else(|| text.parse().ok().and_then(operation))
+        .ok_or_else(|| format!("unknown opcode {:?}", text))?;
+    Ok(op.opcode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> Debugger {
+        Debugger::new(IntComputer::load(vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9]))
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut dbg = debugger();
+        dbg.add_breakpoint(Breakpoint::Address(9));
+        assert_eq!(dbg.resume(), Ok(Stop::State(ExecutionState::WaitingInput)));
+        dbg.computer_mut().write(5);
+        assert_eq!(dbg.resume(), Ok(Stop::Breakpoint(Breakpoint::Address(9))));
+        assert_eq!(dbg.computer().position(), 9);
+        assert_eq!(dbg.resume(), Ok(Stop::State(ExecutionState::Halted)));
+        assert_eq!(dbg.computer_mut().read(), Some(1));
+
+        let mut dbg = debugger();
+        dbg.computer_mut().write(0);
+        dbg.add_breakpoint(Breakpoint::Opcode(4));
+        assert_eq!(dbg.resume(), Ok(Stop::Breakpoint(Breakpoint::Opcode(4))));
+        assert!(dbg.remove_breakpoint(Breakpoint::Opcode(4)));
+        assert_eq!(dbg.resume(), Ok(Stop::State(ExecutionState::Halted)));
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut dbg = debugger();
+        assert_eq!(dbg.command("list 0 2"), "    0: in -> [12]\n    2: jf [12], [15]");
+        assert_eq!(dbg.command("input 7"), "queued 1 values");
+        assert_eq!(dbg.command("break op out"), "breakpoint on opcode 4");
+        assert_eq!(dbg.command("c"),
+            "breakpoint on opcode 4\nip=9 rb=0 state=Running\n    9: out [13]");
+        assert_eq!(dbg.command("x 12 3"), "   12: 7, 1, 1");
+        assert_eq!(dbg.command("poke 13 42"), "   13: 42");
+        assert_eq!(dbg.command("step"), "ip=11 rb=0 state=Running\n   11: halt");
+        assert_eq!(dbg.command("o"), "42");
+        assert_eq!(dbg.command("step 5"), "ip=11 rb=0 state=Halted\n   11: halt");
+        assert_eq!(dbg.command("break x"), "error: invalid number \"x\"");
+        assert_eq!(dbg.command("delete 3"), "error: no such breakpoint");
+        assert_eq!(dbg.command("poke 99999999999999 1"),
+            "error: address 99999999999999 beyond memory limit 16777216");
+        assert_eq!(dbg.command("mem 18446744073709551615 2"), "error: address 18446744073709551615 out of range");
+        assert_eq!(dbg.command("x 0 1000000000"), "error: at most 1000 at a time");
+        assert_eq!(dbg.command("list 18446744073709551614"), "error: address 18446744073709551614 out of range");
+    }
+
+    #[test]
+    fn test_watch_commands() {
+        let mut dbg = debugger();
+        assert_eq!(dbg.command("watch 12-13 =1"), "watching 12..=13");
+        assert_eq!(dbg.command("input 7"), "queued 1 values");
+        assert_eq!(dbg.command("c"),
+            "watchpoint: write [13] = 1 at 5\nip=9 rb=0 state=Paused\n    9: out [13]");
+        assert_eq!(dbg.command("unwatch"), "watchpoints cleared");
+        assert_eq!(dbg.command("watch 13 x"), "error: invalid access \"x\", use r, w or =value");
+        assert_eq!(dbg.command("c"), "Halted\nip=11 rb=0 state=Halted\n   11: halt");
+    }
+}
@@ -0,0 +1,596 @@
+use crate::disasm::instruction_starts_from;
+use crate::instruction::{Instruction, Mode, Parameter};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Memory cells referenced at least this often get a variable name.
+const VARIABLE_USES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Condition {
+    lhs: Parameter,
+    op: &'static str,
+    rhs: Parameter
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        let op = match self.op {
+            "<" => ">=",
+            ">=" => "<",
+            "==" => "!=",
+            _ => "=="
+        };
+        Condition { op, ..self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Address(usize),
+    Computed(Parameter)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Instruction(Instruction),
+    /// A jump, taken when `condition` holds or always if it is `None`.
+    Jump { condition: Option<Condition>, target: Target },
+    /// A return address stored right before a jump to the function.
+    Call(usize),
+    /// An unconditional jump to an address kept on the relative stack.
+    Return,
+    /// A jump that is never taken.
+    Nop,
+    Halt,
+    /// A word that is not an instruction, which the code before it falls
+    /// through into.
+    Data
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Statement {
+    address: usize,
+    kind: Kind
+}
+
+/// Where `break` and `continue` go inside the loop being rendered.
+#[derive(Debug, Clone, Copy, Default)]
+struct Context {
+    top: Option<usize>,
+    exit: Option<usize>
+}
+
+struct Decompiler {
+    statements: Vec<Statement>,
+    index: HashMap<usize, usize>,
+    /// For each statement, the statements that jump to it.
+    incoming: Vec<Vec<usize>>,
+    functions: BTreeSet<usize>,
+    names: BTreeMap<i64, String>,
+    labels: BTreeSet<usize>,
+    lines: Vec<(Option<usize>, usize, String)>
+}
+
+/// Decompiles the code reachable from address 0 into C-like pseudocode.
+/// Comparisons feeding a jump become conditions, jump patterns become
+/// `while`, `do`/`while`, `loop` and `if`/`else`, and a return address
+/// stored just before a jump becomes a call. Anything else is a `goto`.
+pub fn decompile(program: &[i64]) -> String {
+    let (statements, functions) = statements(program);
+    let index: HashMap<usize, usize> = statements.iter().enumerate()
+        .map(|(i, statement)| (statement.address, i))
+        .collect();
+    let mut incoming = vec![Vec::new(); statements.len()];
+    for (source, statement) in statements.iter().enumerate() {
+        if let Kind::Jump { target: Target::Address(target), .. } = statement.kind {
+            if let Some(&i) = index.get(&target) {
+                incoming[i].push(source);
+            }
+        }
+    }
+    let names = variables(&statements);
+    let mut decompiler = Decompiler {
+        statements,
+        index,
+        incoming,
+        functions,
+        names,
+        labels: BTreeSet::new(),
+        lines: Vec::new()
+    };
+    decompiler.render(0, decompiler.statements.len(), 0, Context::default());
+    decompiler.output()
+}
+
+/// Decodes the reachable instructions into statements, folding the
+/// comparison and call idioms. Also returns the called addresses.
+fn statements(program: &[i64]) -> (Vec<Statement>, BTreeSet<usize>) {
+    // Code after a call is only reached through a computed return, so
+    // return addresses are added as entry points until none are new.
+    let mut entries = vec![0];
+    let (starts, instructions) = loop {
+        let starts = instruction_starts_from(program, &entries);
+        let instructions: Vec<Instruction> = (0..program.len())
+            .filter(|address| starts[*address])
+            .map(|address| Instruction::decode(program, address).unwrap())
+            .collect();
+        let returns: Vec<usize> = instructions.windows(2)
+            .filter_map(|pair| return_address(&pair[0], &pair[1]))
+            .filter(|address| *address < program.len() && !starts[*address] && !entries.contains(address))
+            .collect();
+        if returns.is_empty() {
+            break (starts, instructions)
+        }
+        entries.extend(returns);
+    };
+
+    let mut leaders: BTreeSet<usize> = instructions.iter().filter_map(|i| i.jump_target()).collect();
+    leaders.extend(entries);
+    let mut reads: HashMap<(i64, i64), usize> = HashMap::new();
+    for parameter in instructions.iter().flat_map(|i| i.inputs()) {
+        if parameter.mode != Mode::Immediate {
+            *reads.entry(key(parameter)).or_insert(0) += 1;
+        }
+    }
+
+    let data: Vec<Statement> = instructions.iter()
+        .filter(|i| i.branches().1 && starts.get(i.next()) != Some(&true))
+        .map(|i| Statement { address: i.next(), kind: Kind::Data })
+        .collect();
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut functions = BTreeSet::new();
+    for instruction in instructions {
+        let previous = match statements.last() {
+            Some(Statement { kind: Kind::Instruction(previous), .. })
+                if previous.next() == instruction.address && !leaders.contains(&instruction.address) =>
+                Some(previous.clone()),
+            _ => None
+        };
+        let mut address = instruction.address;
+        let kind = match instruction.operation.opcode {
+            99 => Kind::Halt,
+            5 | 6 => {
+                let jump_if_true = instruction.operation.opcode == 5;
+                let (test, to) = (instruction.parameters[0], instruction.parameters[1]);
+                let target = match to {
+                    Parameter { mode: Mode::Immediate, value } if value >= 0 => Target::Address(value as usize),
+                    _ => Target::Computed(to)
+                };
+                let condition = match (test.mode, &previous) {
+                    (Mode::Immediate, _) if (test.value != 0) != jump_if_true => {
+                        statements.push(Statement { address, kind: Kind::Nop });
+                        continue
+                    },
+                    (Mode::Immediate, _) => None,
+                    (_, Some(previous)) if folds_into(previous, &test) && reads[&key(&test)] == 1 => {
+                        statements.pop();
+                        address = previous.address;
+                        let op = if previous.operation.opcode == 7 { "<" } else { "==" };
+                        let condition = Condition { lhs: previous.parameters[0], op, rhs: previous.parameters[1] };
+                        Some(if jump_if_true { condition } else { condition.negate() })
+                    },
+                    _ => {
+                        let op = if jump_if_true { "!=" } else { "==" };
+                        Some(Condition { lhs: test, op, rhs: Parameter { mode: Mode::Immediate, value: 0 } })
+                    }
+                };
+                match (condition, target) {
+                    (None, Target::Address(function)) if previous.as_ref()
+                        .and_then(|previous| return_address(previous, &instruction)).is_some() => {
+                        statements.pop();
+                        address = previous.map_or(address, |previous| previous.address);
+                        functions.insert(function);
+                        Kind::Call(function)
+                    },
+                    (None, Target::Computed(Parameter { mode: Mode::Relative, .. })) => Kind::Return,
+                    (condition, target) => Kind::Jump { condition, target }
+                }
+            },
+            _ => Kind::Instruction(instruction)
+        };
+        statements.push(Statement { address, kind });
+    }
+    statements.extend(data);
+    statements.sort_by_key(|statement| statement.address);
+    (statements, functions)
+}
+
+/// If `store` writes a constant and `jump` right after it always jumps to
+/// an immediate address, and the constant is the address after the jump,
+/// that address is where the called code returns to.
+fn return_address(store: &Instruction, jump: &Instruction) -> Option<usize> {
+    if jump.address != store.next() || jump.jump_target().is_none() {
+        return None
+    }
+    match jump.parameters[0] {
+        Parameter { mode: Mode::Immediate, value } if (value != 0) == (jump.operation.opcode == 5) => (),
+        _ => return None
+    }
+    let value = match (store.operation.opcode, store.parameters.as_slice()) {
+        (1, [a, b, _]) if a.mode == Mode::Immediate && b.mode == Mode::Immediate => a.value.checked_add(b.value)?,
+        (2, [a, b, _]) if a.mode == Mode::Immediate && b.mode == Mode::Immediate => a.value.checked_mul(b.value)?,
+        _ => return None
+    };
+    if value == jump.next() as i64 {
+        Some(jump.next())
+    } else {
+        None
+    }
+}
+
+/// Whether `previous` is a comparison whose result is the jump's `test`.
+fn folds_into(previous: &Instruction, test: &Parameter) -> bool {
+    matches!(previous.operation.opcode, 7 | 8) && previous.target() == Some(test)
+}
+
+/// Writes adding a negative constant as subtracting.
+fn subtraction(op: &'static str, parameter: &Parameter, operand: &str) -> (&'static str, String) {
+    match parameter {
+        Parameter { mode: Mode::Immediate, value } if op == "+" && *value < 0 && *value != i64::MIN =>
+            ("-", (-value).to_string()),
+        _ => (op, operand.to_string())
+    }
+}
+
+fn key(parameter: &Parameter) -> (i64, i64) {
+    (parameter.mode.digit(), parameter.value)
+}
+
+/// Names the position-mode cells the statements use most, in address
+/// order.
+fn variables(statements: &[Statement]) -> BTreeMap<i64, String> {
+    let mut uses: BTreeMap<i64, usize> = BTreeMap::new();
+    let mut count = |parameter: &Parameter| if parameter.mode == Mode::Position {
+        *uses.entry(parameter.value).or_insert(0) += 1;
+    };
+    for statement in statements {
+        match &statement.kind {
+            Kind::Instruction(instruction) => instruction.parameters.iter().for_each(&mut count),
+            Kind::Jump { condition, target } => {
+                if let Some(condition) = condition {
+                    count(&condition.lhs);
+                    count(&condition.rhs);
+                }
+                if let Target::Computed(parameter) = target {
+                    count(parameter);
+                }
+            },
+            _ => ()
+        }
+    }
+    uses.into_iter()
+        .filter(|(_, uses)| *uses >= VARIABLE_USES)
+        .enumerate()
+        .map(|(i, (address, _))| (address, format!("v{}", i + 1)))
+        .collect()
+}
+
+impl Decompiler {
+    fn address(&self, i: usize) -> Option<usize> {
+        self.statements.get(i).map(|statement| statement.address)
+    }
+
+    /// Whether control can only enter `lo..hi` at `lo`.
+    fn closed(&self, lo: usize, hi: usize) -> bool {
+        (lo + 1..hi).all(|i| self.incoming[i].iter().all(|source| (lo..hi).contains(source)))
+    }
+
+    fn jump(&self, i: usize) -> Option<(Option<Condition>, Target)> {
+        match self.statements[i].kind {
+            Kind::Jump { condition, target } => Some((condition, target)),
+            _ => None
+        }
+    }
+
+    fn line(&mut self, address: Option<usize>, depth: usize, text: String) {
+        self.lines.push((address, depth, text));
+    }
+
+    fn render(&mut self, lo: usize, hi: usize, depth: usize, context: Context) {
+        let mut k = lo;
+        while k < hi {
+            let address = self.statements[k].address;
+
+            // A loop: the last jump back to `k` that closes a region.
+            let back = if context.top == Some(address) {
+                None
+            } else {
+                self.incoming[k].iter().copied()
+                    .filter(|m| (k..hi).contains(m) && self.closed(k, m + 1))
+                    .max()
+            };
+            if let Some(m) = back {
+                let exit = self.address(m + 1);
+                let (condition, _) = self.jump(m).unwrap();
+                match (condition, self.jump(k)) {
+                    (None, Some((Some(test), Target::Address(target)))) if k < m && Some(target) == exit => {
+                        let text = format!("while ({}) {{", self.condition(test.negate()));
+                        self.line(Some(address), depth, text);
+                        self.render(k + 1, m, depth + 1, Context { top: Some(address), exit });
+                    },
+                    (None, _) => {
+                        self.line(Some(address), depth, "loop {".to_string());
+                        self.render(k, m, depth + 1, Context { top: Some(address), exit });
+                    },
+                    (Some(condition), _) => {
+                        self.line(Some(address), depth, "do {".to_string());
+                        self.render(k, m, depth + 1, Context { top: None, exit });
+                        let text = format!("}} while ({});", self.condition(condition));
+                        self.line(None, depth, text);
+                        k = m + 1;
+                        continue
+                    }
+                }
+                self.line(None, depth, "}".to_string());
+                k = m + 1;
+                continue
+            }
+
+            // A forward conditional jump over a closed region: `if`, with an
+            // `else` when the region ends in a jump over another one.
+            if let Some((Some(test), Target::Address(target))) = self.jump(k) {
+                let n = self.index.get(&target).copied().filter(|n| k < *n && *n <= hi);
+                let escapes = Some(target) == context.top || Some(target) == context.exit;
+                if let (Some(n), false) = (n, escapes) {
+                    // Skipping over a lone jump is a jump on the opposite
+                    // condition.
+                    if let Some((None, skipped)) = self.jump(k + 1).filter(|_| n == k + 2) {
+                        if self.incoming[k + 1].is_empty() {
+                            let text = self.jump_text(Some(test.negate()), skipped, context);
+                            self.line(Some(address), depth, text);
+                            k = n;
+                            continue
+                        }
+                    }
+                    if self.closed(k, n) {
+                        self.line(Some(address), depth, format!("if ({}) {{", self.condition(test.negate())));
+                        let otherwise = match self.jump(n - 1) {
+                            Some((None, Target::Address(end))) if n - 1 > k => self.index.get(&end).copied()
+                                .filter(|n2| n < *n2 && *n2 <= hi && self.closed(k, *n2)),
+                            _ => None
+                        };
+                        match otherwise {
+                            Some(n2) => {
+                                self.render(k + 1, n - 1, depth + 1, context);
+                                self.line(None, depth, "} else {".to_string());
+                                self.render(n, n2, depth + 1, context);
+                                k = n2;
+                            },
+                            None => {
+                                self.render(k + 1, n, depth + 1, context);
+                                k = n;
+                            }
+                        }
+                        self.line(None, depth, "}".to_string());
+                        continue
+                    }
+                }
+            }
+
+            let text = self.statement(k, context);
+            self.line(Some(address), depth, text);
+            k += 1;
+        }
+    }
+
+    fn statement(&mut self, i: usize, context: Context) -> String {
+        match self.statements[i].kind.clone() {
+            Kind::Instruction(instruction) => format!("{};", self.instruction(&instruction)),
+            Kind::Jump { target: Target::Address(target), .. } if self.address(i + 1) == Some(target) =>
+                String::new(),
+            Kind::Jump { condition, target } => self.jump_text(condition, target, context),
+            Kind::Call(function) => format!("{}();", self.label(function)),
+            Kind::Return => "return;".to_string(),
+            Kind::Nop => String::new(),
+            Kind::Halt => "halt;".to_string(),
+            Kind::Data => format!("/* falls into data at {} */", self.statements[i].address)
+        }
+    }
+
+    fn jump_text(&mut self, condition: Option<Condition>, target: Target, context: Context) -> String {
+        let action = match target {
+            Target::Address(target) if Some(target) == context.exit => "break".to_string(),
+            Target::Address(target) if Some(target) == context.top => "continue".to_string(),
+            Target::Address(target) => {
+                self.labels.insert(target);
+                format!("goto {}", self.label(target))
+            },
+            Target::Computed(parameter) => format!("goto *{}", self.operand(&parameter))
+        };
+        match condition {
+            Some(condition) => format!("if ({}) {};", self.condition(condition), action),
+            None => format!("{};", action)
+        }
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> String {
+        let operands: Vec<String> = instruction.parameters.iter().map(|p| self.operand(p)).collect();
+        let parameters = &instruction.parameters;
+        match instruction.operation.opcode {
+            1 | 2 => {
+                let (op, identity) = if instruction.operation.opcode == 1 { ("+", 0) } else { ("*", 1) };
+                let is_identity = |p: &Parameter| p.mode == Mode::Immediate && p.value == identity;
+                if is_identity(&parameters[0]) {
+                    format!("{} = {}", operands[2], operands[1])
+                } else if is_identity(&parameters[1]) {
+                    format!("{} = {}", operands[2], operands[0])
+                } else if parameters[0] == parameters[2] {
+                    let (op, operand) = subtraction(op, &parameters[1], &operands[1]);
+                    format!("{} {}= {}", operands[2], op, operand)
+                } else if parameters[1] == parameters[2] {
+                    let (op, operand) = subtraction(op, &parameters[0], &operands[0]);
+                    format!("{} {}= {}", operands[2], op, operand)
+                } else {
+                    let (op, operand) = subtraction(op, &parameters[1], &operands[1]);
+                    format!("{} = {} {} {}", operands[2], operands[0], op, operand)
+                }
+            },
+            3 => format!("{} = input()", operands[0]),
+            4 => format!("output({})", operands[0]),
+            7 => format!("{} = {} < {}", operands[2], operands[0], operands[1]),
+            8 => format!("{} = {} == {}", operands[2], operands[0], operands[1]),
+            _ => format!("rb += {}", operands[0])
+        }
+    }
+
+    fn condition(&self, condition: Condition) -> String {
+        format!("{} {} {}", self.operand(&condition.lhs), condition.op, self.operand(&condition.rhs))
+    }
+
+    fn operand(&self, parameter: &Parameter) -> String {
+        match parameter.mode {
+            Mode::Immediate => parameter.value.to_string(),
+            Mode::Position => self.names.get(&parameter.value).cloned()
+                .unwrap_or_else(|| format!("mem[{}]", parameter.value)),
+            Mode::Relative => format!("rb[{}]", parameter.value)
+        }
+    }
+
+    fn label(&self, address: usize) -> String {
+        if self.functions.contains(&address) {
+            format!("func_{}", address)
+        } else {
+            format!("L{}", address)
+        }
+    }
+
+    fn output(&self) -> String {
+        let mut output = String::new();
+        for (address, name) in &self.names {
+            output.push_str(&format!("// {} = mem[{}]\n", name, address));
+        }
+        if !self.names.is_empty() {
+            output.push('\n');
+        }
+        let mut labelled = BTreeSet::new();
+        for (address, depth, text) in &self.lines {
+            if let Some(address) = address {
+                if labelled.insert(*address) {
+                    if self.functions.contains(address) {
+                        output.push_str(&format!("\n{}:\n", self.label(*address)));
+                    } else if self.labels.contains(address) {
+                        output.push_str(&format!("{}{}:\n", "    ".repeat(*depth), self.label(*address)));
+                    }
+                }
+            }
+            if !text.is_empty() {
+                output.push_str(&format!("{}{}\n", "    ".repeat(*depth + 1), text));
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn test_while() {
+        // Doubles its input until it reaches 100, then prints it.
+        let program = [3,19,1007,19,100,20,1006,20,16,1002,19,2,19,1105,1,2,4,19,99,0,0];
+        assert_eq!(decompile(&program), concat!(
+            "// v1 = mem[19]\n",
+            "\n",
+            "    v1 = input();\n",
+            "    while (v1 < 100) {\n",
+            "        v1 *= 2;\n",
+            "    }\n",
+            "    output(v1);\n",
+            "    halt;\n"));
+    }
+
+    #[test]
+    fn test_if_else_do_while() {
+        let program = assemble("
+                    in -> [x]
+                    lt [x], #10 -> [t]
+                    jf [t], #big
+                    out #1
+                    jt #1, #count
+            big:    out #2
+            count:  out [x]
+                    add [x], #-1 -> [x]
+                    jt [x], #count
+                    halt
+            x:      data 0
+            t:      data 0
+        ").unwrap();
+        assert_eq!(decompile(&program), concat!(
+            "// v1 = mem[26]\n",
+            "\n",
+            "    v1 = input();\n",
+            "    if (v1 < 10) {\n",
+            "        output(1);\n",
+            "    } else {\n",
+            "        output(2);\n",
+            "    }\n",
+            "    do {\n",
+            "        output(v1);\n",
+            "        v1 -= 1;\n",
+            "    } while (v1 != 0);\n",
+            "    halt;\n"));
+    }
+
+    #[test]
+    fn test_call() {
+        let program = assemble("
+                    arb #stack
+                    in -> [n]
+                    add #back, #0 -> [rb+0]
+                    jt #1, #double
+            back:   out [n]
+                    halt
+            double: mul [n], #2 -> [n]
+                    jf #0, [rb+0]
+            n:      data 0
+            stack:  data 0
+        ").unwrap();
+        assert_eq!(decompile(&program), concat!(
+            "// v1 = mem[21]\n",
+            "\n",
+            "    rb += 22;\n",
+            "    v1 = input();\n",
+            "    func_14();\n",
+            "    output(v1);\n",
+            "    halt;\n",
+            "\n",
+            "func_14:\n",
+            "    v1 *= 2;\n",
+            "    return;\n"));
+    }
+
+    #[test]
+    fn test_falls_into_data() {
+        assert_eq!(decompile(&[1101,1,1,3,0,0,0]), concat!(
+            "    mem[3] = 1 + 1;\n",
+            "    /* falls into data at 4 */\n"));
+    }
+
+    #[test]
+    fn test_goto() {
+        // A loop entered in the middle has no structured form.
+        let program = assemble("
+                    in -> [x]
+                    jt [x], #b
+            a:      out #1
+            b:      out #2
+                    jt [x], #a
+                    halt
+            x:      data 0
+        ").unwrap();
+        assert_eq!(decompile(&program), concat!(
+            "// v1 = mem[13]\n",
+            "\n",
+            "    v1 = input();\n",
+            "    if (v1 != 0) goto L7;\n",
+            "L5:\n",
+            "    output(1);\n",
+            "L7:\n",
+            "    output(2);\n",
+            "    if (v1 != 0) goto L5;\n",
+            "    halt;\n"));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::instruction::Instruction;
+
+use std::fmt;
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    Code(Instruction),
+    Data { address: usize, values: Vec<i64> }
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Code(instruction) => instruction.address,
+            Line::Data { address, .. } => *address
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>5}: ", self.address())?;
+        match self {
+            Line::Code(instruction) => write!(f, "{}", instruction),
+            Line::Data { values, .. } => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "data {}", values.join(", "))
+            }
+        }
+    }
+}
+
+/// Finds the addresses where instructions start by following control flow
+/// from address 0. Jumps are only followed when their target is immediate,
+/// and not at all when an immediate condition means they are never taken.
+pub fn instruction_starts(program: &[i64]) -> Vec<bool> {
+    instruction_starts_from(program, &[0])
+}
+
+/// Like `instruction_starts`, following control flow from each of `entries`.
+pub fn instruction_starts_from(program: &[i64], entries: &[usize]) -> Vec<bool> {
+    let mut starts = vec![false; program.len()];
+    let mut covered = vec![false; program.len()];
+    let mut pending: Vec<usize> = entries.iter().rev().copied().collect();
+    while let Some(address) = pending.pop() {
+        if address >= program.len() || covered[address] {
+            continue
+        }
+        let instruction = match Instruction::decode(program, address) {
+            Some(instruction) if instruction.next() <= program.len() => instruction,
+            _ => continue
+        };
+        if covered[address..instruction.next()].iter().any(|c| *c) {
+            continue
+        }
+        for c in &mut covered[address..instruction.next()] {
+            *c = true;
+        }
+        starts[address] = true;
+        let (taken, falls) = instruction.branches();
+        if let Some(target) = instruction.jump_target().filter(|_| taken) {
+            pending.push(target);
+        }
+        if falls {
+            pending.push(instruction.next());
+        }
+    }
+    starts
+}
+
+/// Lists code as instructions and everything else as data. An instruction
+/// whose word has mode digits its operation ignores is listed as data, so
+/// that assembling the listing gives back the same words.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    let starts = instruction_starts(program);
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        if starts[address] {
+            let instruction = Instruction::decode(program, address).unwrap();
+            address = instruction.next();
+            if instruction.encode() == instruction.raw {
+                lines.push(Line::Code(instruction));
+            } else {
+                let values = program[instruction.address..address].to_vec();
+                lines.push(Line::Data { address: instruction.address, values });
+            }
+        } else {
+            let start = address;
+            while address < program.len() && !starts[address] && address - start < DATA_PER_LINE {
+                address += 1;
+            }
+            lines.push(Line::Data { address: start, values: program[start..address].to_vec() });
+        }
+    }
+    lines
+}
+
+pub fn listing(program: &[i64]) -> String {
+    let mut output = String::new();
+    for line in disassemble(program) {
+        output.push_str(&line.to_string());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing() {
+        let program = vec![1,9,10,3,2,3,11,0,99,30,40,50];
+        assert_eq!(listing(&program),
+            "    0: add [9], [10] -> [3]\n    4: mul [3], [11] -> [0]\n    8: halt\n    9: data 30, 40, 50\n");
+    }
+
+    #[test]
+    fn test_jumps() {
+        let program = vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1];
+        let lines = disassemble(&program);
+        let addresses: Vec<usize> = lines.iter().map(|l| l.address()).collect();
+        assert_eq!(addresses, vec![0,2,5,9,11,12]);
+        assert_eq!(lines[5], Line::Data { address: 12, values: vec![1] });
+    }
+
+    #[test]
+    fn test_unconditional_jump() {
+        let program = vec![1105,1,4,98,99];
+        assert_eq!(listing(&program), "    0: jt #1, #4\n    3: data 98\n    4: halt\n");
+    }
+
+    #[test]
+    fn test_never_taken() {
+        let program = vec![1106,1,5,99,0,1,0,0,0,99];
+        assert_eq!(listing(&program), "    0: jf #1, #5\n    3: halt\n    4: data 0, 1, 0, 0, 0, 99\n");
+    }
+
+    #[test]
+    fn test_ignored_modes() {
+        let program = vec![10104,7,104,8,99];
+        assert_eq!(listing(&program), "    0: data 10104, 7\n    2: out #8\n    4: halt\n");
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    UnknownOpcode(i64),
+    InvalidMode { parameter: usize, mode: i64 },
+    NegativeAddress(i64),
+    AddressOutOfRange { address: usize, limit: usize },
+    ImmediateWrite { parameter: usize },
+    ArithmeticOverflow
+}
+
+/// A fault raised while executing an instruction, along with the
+/// instruction pointer and raw instruction word that caused it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntcodeError {
+    pub position: usize,
+    pub instruction: i64,
+    pub kind: ErrorKind
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownOpcode(opcode) =>
+                write!(f, "unknown opcode {}", opcode),
+            ErrorKind::InvalidMode { parameter, mode } =>
+                write!(f, "invalid mode {} for parameter {}", mode, parameter),
+            ErrorKind::NegativeAddress(address) =>
+                write!(f, "negative address {}", address),
+            ErrorKind::AddressOutOfRange { address, limit } =>
+                write!(f, "address {} beyond memory limit {}", address, limit),
+            ErrorKind::ImmediateWrite { parameter } =>
+                write!(f, "write parameter {} in immediate mode", parameter),
+            ErrorKind::ArithmeticOverflow =>
+                write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {} (instruction {})", self.kind, self.position, self.instruction)
+    }
+}
+
+impl std::error::Error for IntcodeError {}
@@ -0,0 +1,402 @@
+use crate::{ExecutionState, IntComputer};
+
+use std::convert::TryInto;
+
+/// Cells in the data block after the code. At least three, so that an
+/// interpreter reading a full instruction at the final halt stays in
+/// bounds.
+const DATA_CELLS: usize = 8;
+
+/// A xorshift generator, so that a failing case can be replayed from its
+/// seed.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number in `low..=high`.
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next_u64() % (high - low + 1) as u64) as i64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    /// A cell of the data block, in position mode.
+    Data(usize),
+    Immediate(i64),
+    /// The address of an instruction, as an immediate. One past the last
+    /// instruction is the final halt.
+    Label(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Op {
+    pub opcode: i64,
+    pub operands: Vec<Operand>
+}
+
+/// A generated program: its instructions, then a halt, then the data
+/// block. Operands refer to data cells and instructions by index, so
+/// instructions can be removed while shrinking without breaking the rest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub ops: Vec<Op>,
+    pub data: Vec<i64>
+}
+
+impl Program {
+    /// Lays the program out in memory.
+    pub fn to_vec(&self) -> Vec<i64> {
+        let mut addresses = Vec::new();
+        let mut address = 0;
+        for op in &self.ops {
+            addresses.push(address as i64);
+            address += 1 + op.operands.len();
+        }
+        addresses.push(address as i64);
+        let data = address as i64 + 1;
+
+        let mut program = Vec::new();
+        for op in &self.ops {
+            let modes = op.operands.iter().rev()
+                .fold(0, |modes, operand| modes * 10 + !matches!(operand, Operand::Data(_)) as i64);
+            program.push(modes * 100 + op.opcode);
+            program.extend(op.operands.iter().map(|operand| match *operand {
+                Operand::Data(index) => data + index as i64,
+                Operand::Immediate(value) => value,
+                Operand::Label(index) => addresses[index]
+            }));
+        }
+        program.push(99);
+        program.extend(&self.data);
+        program
+    }
+
+    /// Shrinks a program `fails` accepts, by removing instructions and
+    /// data cells and zeroing values, for as long as it still fails.
+    pub fn shrink<F>(mut self, mut fails: F) -> Program where F: FnMut(&Program) -> bool {
+        loop {
+            let candidate = self.candidates().into_iter().find(|candidate| fails(candidate));
+            match candidate {
+                Some(candidate) => self = candidate,
+                None => return self
+            }
+        }
+    }
+
+    /// Every program one simplification away from this one.
+    fn candidates(&self) -> Vec<Program> {
+        let mut candidates = Vec::new();
+        for i in 0..self.ops.len() {
+            let mut candidate = self.clone();
+            candidate.ops.remove(i);
+            for operand in candidate.ops.iter_mut().flat_map(|op| op.operands.iter_mut()) {
+                if let Operand::Label(index) = operand {
+                    if *index > i {
+                        *index -= 1;
+                    }
+                }
+            }
+            candidates.push(candidate);
+        }
+        let used = self.ops.iter().flat_map(|op| &op.operands).filter_map(|operand| match operand {
+            Operand::Data(index) => Some(*index + 1),
+            _ => None
+        }).max().unwrap_or(0);
+        if self.data.len() > used.max(3) {
+            let mut candidate = self.clone();
+            candidate.data.pop();
+            candidates.push(candidate);
+        }
+        for i in 0..self.data.len() {
+            if self.data[i] != 0 {
+                let mut candidate = self.clone();
+                candidate.data[i] = 0;
+                candidates.push(candidate);
+            }
+        }
+        for (i, op) in self.ops.iter().enumerate() {
+            for (j, operand) in op.operands.iter().enumerate() {
+                if let Operand::Immediate(value) = operand {
+                    if *value != 0 {
+                        let mut candidate = self.clone();
+                        candidate.ops[i].operands[j] = Operand::Immediate(0);
+                        candidates.push(candidate);
+                    }
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Generates random valid programs. Results are always written to the
+/// data block, so programs never modify their own code.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    opcodes: Vec<i64>,
+    immediates: bool,
+    size: usize
+}
+
+impl Generator {
+    /// Up to `size` adds and multiplies in position mode on non-negative
+    /// data, which any day 2 interpreter can run.
+    pub fn arithmetic(size: usize) -> Generator {
+        Generator { opcodes: vec![1, 2], immediates: false, size }
+    }
+
+    /// Up to `size` instructions of every opcode but `arb`, with position
+    /// and immediate operands. Jumps go to generated instructions, so
+    /// programs may loop forever and should be run with a step limit.
+    pub fn full(size: usize) -> Generator {
+        Generator { opcodes: (1..=8).collect(), immediates: true, size }
+    }
+
+    pub fn generate(&self, rng: &mut Rng) -> Program {
+        let count = rng.below(self.size + 1);
+        let data = (0..DATA_CELLS).map(|_| rng.range(0, 20)).collect();
+        let ops = (0..count).map(|_| {
+            let opcode = self.opcodes[rng.below(self.opcodes.len())];
+            let operands = match opcode {
+                1 | 2 | 7 | 8 => vec![self.read(rng), self.read(rng), self.write(rng)],
+                3 => vec![self.write(rng)],
+                4 => vec![self.read(rng)],
+                _ => vec![self.read(rng), Operand::Label(rng.below(count + 1))]
+            };
+            Op { opcode, operands }
+        }).collect();
+        Program { ops, data }
+    }
+
+    fn read(&self, rng: &mut Rng) -> Operand {
+        if self.immediates && rng.below(2) == 0 {
+            Operand::Immediate(rng.range(-10, 10))
+        } else {
+            self.write(rng)
+        }
+    }
+
+    fn write(&self, rng: &mut Rng) -> Operand {
+        Operand::Data(rng.below(DATA_CELLS))
+    }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    Halted,
+    WaitingInput,
+    /// The step limit was reached.
+    OutOfSteps,
+    /// An error at this position.
+    Error(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub stop: Stop,
+    pub memory: Vec<i64>,
+    pub output: Vec<i64>,
+    pub steps: u64
+}
+
+/// Runs `program` on `IntComputer` for at most `steps` instructions.
+pub fn run(program: &[i64], input: &[i64], steps: u64) -> Outcome {
+    let mut ic = IntComputer::load(program.to_vec());
+    for value in input {
+        ic.write(*value);
+    }
+    let stop = match ic.execute_for(steps) {
+        Ok(ExecutionState::Halted) => Stop::Halted,
+        Ok(ExecutionState::WaitingInput) => Stop::WaitingInput,
+        Ok(_) => Stop::OutOfSteps,
+        Err(error) => Stop::Error(error.position)
+    };
+    let output = std::iter::from_fn(|| ic.read()).collect();
+    Outcome { stop, memory: ic.memory().to_vec(), output, steps: ic.cycles() }
+}
+
+/// A deliberately plain interpreter for everything but `arb` and relative
+/// mode, to check `IntComputer` against.
+pub fn reference(program: &[i64], input: &[i64], steps: u64) -> Outcome {
+    let mut memory = program.to_vec();
+    let mut input = input.iter();
+    let mut output = Vec::new();
+    let mut position = 0;
+    let mut count = 0;
+    let stop = loop {
+        if count == steps {
+            break Stop::OutOfSteps
+        }
+        match reference_step(&mut memory, &mut position, &mut input, &mut output) {
+            Some(Stop::Halted) => {
+                count += 1;
+                break Stop::Halted
+            },
+            Some(stop) => break stop,
+            None => count += 1
+        }
+    };
+    Outcome { stop, memory, output, steps: count }
+}
+
+/// Runs one instruction; `None` if execution goes on.
+fn reference_step(memory: &mut Vec<i64>, position: &mut usize, input: &mut std::slice::Iter<i64>,
+    output: &mut Vec<i64>) -> Option<Stop> {
+    let at = *position;
+    let word = |memory: &Vec<i64>, address: usize| memory.get(address).copied().unwrap_or(0);
+    let instruction = word(memory, at);
+    let opcode = instruction % 100;
+    let mode = |n: u32| instruction / 10_i64.pow(n + 1) % 10;
+    let address = |memory: &Vec<i64>, n: usize| -> Option<usize> {
+        if mode(n as u32) != 0 {
+            return None
+        }
+        word(memory, at + n).try_into().ok()
+    };
+    let value = |memory: &Vec<i64>, n: usize| -> Option<i64> {
+        match mode(n as u32) {
+            0 => Some(word(memory, address(memory, n)?)),
+            1 => Some(word(memory, at + n)),
+            _ => None
+        }
+    };
+    let error = Some(Stop::Error(at));
+    match opcode {
+        1 | 2 | 7 | 8 => {
+            let (a, b) = match (value(memory, 1), value(memory, 2)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return error
+            };
+            let target = match address(memory, 3) {
+                Some(target) => target,
+                None => return error
+            };
+            let result = match opcode {
+                1 => a.checked_add(b),
+                2 => a.checked_mul(b),
+                7 => Some((a < b) as i64),
+                _ => Some((a == b) as i64)
+            };
+            match result {
+                Some(result) => write(memory, target, result),
+                None => return error
+            }
+            *position += 4;
+        },
+        3 => {
+            let target = match address(memory, 1) {
+                Some(target) => target,
+                None => return error
+            };
+            match input.next() {
+                Some(value) => write(memory, target, *value),
+                None => return Some(Stop::WaitingInput)
+            }
+            *position += 2;
+        },
+        4 => {
+            output.push(match value(memory, 1) {
+                Some(value) => value,
+                None => return error
+            });
+            *position += 2;
+        },
+        5 | 6 => {
+            let (condition, target) = match (value(memory, 1), value(memory, 2)) {
+                (Some(condition), Some(target)) => (condition, target),
+                _ => return error
+            };
+            if (condition != 0) == (opcode == 5) {
+                *position = match target.try_into() {
+                    Ok(target) => target,
+                    Err(_) => return error
+                };
+            } else {
+                *position += 3;
+            }
+        },
+        99 => return Some(Stop::Halted),
+        _ => return error
+    }
+    None
+}
+
+fn write(memory: &mut Vec<i64>, address: usize, value: i64) {
+    if address >= memory.len() {
+        memory.resize(address + 1, 0);
+    }
+    memory[address] = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEPS: u64 = 1_000;
+
+    #[test]
+    fn test_against_reference() {
+        let generator = Generator::full(12);
+        for seed in 0..2_000 {
+            let mut rng = Rng::new(seed);
+            let program = generator.generate(&mut rng);
+            let input: Vec<i64> = (0..rng.below(4)).map(|_| rng.range(-10, 10)).collect();
+            let differs = |program: &Program| {
+                let program = program.to_vec();
+                run(&program, &input, STEPS) != reference(&program, &input, STEPS)
+            };
+            if differs(&program) {
+                let minimal = program.shrink(differs).to_vec();
+                panic!("seed {}: {:?} with input {:?}\nintcode:   {:?}\nreference: {:?}", seed, minimal, input,
+                    run(&minimal, &input, STEPS), reference(&minimal, &input, STEPS));
+            }
+        }
+    }
+
+    #[test]
+    fn test_shrink() {
+        let generator = Generator::full(20);
+        let outputs = |program: &Program| reference(&program.to_vec(), &[], STEPS).output.len();
+        let program = (0..).map(|seed| generator.generate(&mut Rng::new(seed)))
+            .find(|program| program.ops.len() > 5 && outputs(program) > 0)
+            .unwrap();
+        let minimal = program.shrink(|program| outputs(program) > 0);
+        assert_eq!(minimal.ops.len(), 1);
+        assert_eq!(minimal.ops[0].opcode, 4);
+        assert!(minimal.data.iter().all(|value| *value == 0));
+    }
+
+    #[test]
+    fn test_layout() {
+        let program = Program {
+            ops: vec![
+                Op { opcode: 1, operands: vec![Operand::Data(0), Operand::Immediate(-3), Operand::Data(2)] },
+                Op { opcode: 6, operands: vec![Operand::Data(2), Operand::Label(3)] },
+                Op { opcode: 4, operands: vec![Operand::Data(2)] },
+            ],
+            data: vec![5, 0, 0]
+        };
+        assert_eq!(program.to_vec(), vec![1001,10,-3,12,1006,12,9,4,12,99,5,0,0]);
+        assert_eq!(run(&program.to_vec(), &[], STEPS).output, vec![2]);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::instruction::split_opcode;
+use crate::io::{Input, Output};
+use crate::{ExecutionState, IntComputer, MemoryWrite};
+
+use std::collections::VecDeque;
+
+/// What one executed instruction changed, so it can be undone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoRecord {
+    /// Where the instruction ran.
+    pub position: usize,
+    pub relative_base: i64,
+    /// The cycle counter before the instruction ran.
+    pub cycles: u64,
+    /// The machine state before the instruction ran.
+    pub state: ExecutionState,
+    /// Memory size before the instruction, which may have grown it.
+    pub memory_len: usize,
+    pub write: Option<MemoryWrite>,
+    /// The value taken from the input queue, if any.
+    pub input: Option<i64>,
+    /// The value added to the output queue, if any.
+    pub output: Option<i64>
+}
+
+/// An undo log of the most recent instructions a machine executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    records: VecDeque<UndoRecord>,
+    capacity: usize
+}
+
+impl History {
+    /// Keeps at most `capacity` records, dropping the oldest.
+    pub fn new(capacity: usize) -> History {
+        History { records: VecDeque::new(), capacity }
+    }
+
+    pub fn records(&self) -> &VecDeque<UndoRecord> {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, record: UndoRecord) {
+        if self.capacity == 0 {
+            return
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    fn pop(&mut self) -> Option<UndoRecord> {
+        self.records.pop_back()
+    }
+}
+
+/// Reverse execution for machines with a `History` attached.
+impl IntComputer {
+    /// Undoes the last executed instruction. Output values already taken
+    /// with `read` stay taken. Returns `None` once the history runs out.
+    pub fn step_back(&mut self) -> Option<UndoRecord> {
+        let record = self.history.as_mut()?.pop()?;
+        if let Some(write) = &record.write {
+            self.set(write.address, write.old).expect("undone writes are within memory");
+        }
+        self.state.truncate(record.memory_len);
+        if let Some(value) = record.input {
+            self.input.push_front(value);
+        }
+        if record.output.is_some() {
+            self.output.pop_back();
+        }
+        self.position = record.position;
+        self.relative_base = record.relative_base;
+        self.execution_state = record.state.clone();
+        self.cycles = record.cycles;
+        Some(record)
+    }
+
+    /// Steps back until an undone instruction satisfies `stop`, leaving the
+    /// machine about to execute it again. Returns that instruction's record.
+    pub fn step_back_until<F>(&mut self, mut stop: F) -> Option<UndoRecord>
+        where F: FnMut(&UndoRecord) -> bool {
+        while let Some(record) = self.step_back() {
+            if stop(&record) {
+                return Some(record)
+            }
+        }
+        None
+    }
+
+    /// Steps back at least once, until the instruction pointer is `address`.
+    pub fn run_back_to(&mut self, address: usize) -> bool {
+        self.step_back_until(|record| record.position == address).is_some()
+    }
+
+    /// Steps back to the instruction that last wrote `address`.
+    pub fn run_back_to_write(&mut self, address: usize) -> Option<MemoryWrite> {
+        self.step_back_until(|record| record.write.as_ref().map(|w| w.address) == Some(address))
+            .and_then(|record| record.write)
+    }
+}
+
+impl<I: Input, O: Output> IntComputer<I, O> {
+    /// Starts the undo record for the instruction about to run, if history
+    /// is on; `finish_record` fills in what it changed.
+    pub(crate) fn start_record(&self) -> Option<UndoRecord> {
+        self.history.as_ref()?;
+        let output = match split_opcode(self.fetch(self.position)).0 {
+            4 => self.operand_values().first().copied(),
+            _ => None
+        };
+        Some(UndoRecord {
+            position: self.position,
+            relative_base: self.relative_base,
+            cycles: self.cycles,
+            state: self.execution_state.clone(),
+            memory_len: self.state.len(),
+            write: None,
+            input: None,
+            output
+        })
+    }
+
+    pub(crate) fn finish_record(&mut self, mut record: UndoRecord, instruction: i64) {
+        record.write = self.last_write.clone();
+        if split_opcode(instruction).0 == 3 {
+            record.input = record.write.as_ref().map(|write| write.new);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.push(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three self-tests in the style of the aoc5 diagnostics: the second one
+    // fails with code 3.
+    const DIAGNOSTIC: [i64; 15] = [1101,0,0,30,4,30,1101,1,2,31,4,31,104,0,99];
+
+    #[test]
+    fn test_step_back() {
+        let mut ic = IntComputer::load(vec![3,20,1001,20,5,21,4,21,99]);
+        ic.set_history(History::new(100));
+        ic.write(7);
+        assert_eq!(ic.execute(), Ok(&ExecutionState::Halted));
+        let finished = ic.snapshot();
+        assert_eq!(ic.step_back().map(|r| r.position), Some(8));
+        assert_eq!(ic.cycles(), 3);
+        assert_eq!(ic.step_back().and_then(|r| r.output), Some(12));
+        assert_eq!(ic.output().len(), 0);
+        ic.step_back();
+        assert_eq!(ic.memory().len(), 21);
+        assert_eq!(ic.step_back().and_then(|r| r.input), Some(7));
+        assert_eq!(ic.step_back(), None);
+        assert_eq!(ic.memory(), &[3,20,1001,20,5,21,4,21,99]);
+        assert_eq!(ic.position(), 0);
+        assert_eq!(ic.cycles(), 0);
+        assert_eq!(ic.input().iter().copied().collect::<Vec<_>>(), vec![7]);
+        ic.execute().unwrap();
+        assert_eq!(ic.snapshot(), finished);
+    }
+
+    #[test]
+    fn test_rewind_failed_diagnostic() {
+        let mut ic = IntComputer::load(DIAGNOSTIC.to_vec());
+        ic.set_history(History::new(100));
+        ic.execute().unwrap();
+        let outputs: Vec<i64> = std::iter::from_fn(|| ic.read()).collect();
+        assert_eq!(outputs, vec![0, 3, 0]);
+
+        let mut remaining = 2;
+        let failed = ic.step_back_until(|record| record.output.is_some() && {
+            remaining -= 1;
+            remaining == 0
+        });
+        assert_eq!(failed.map(|r| (r.position, r.output)), Some((10, Some(3))));
+        assert_eq!(ic.run_back_to_write(31), Some(MemoryWrite { address: 31, old: 0, new: 3 }));
+        assert_eq!(ic.position(), 6);
+        assert!(ic.run_back_to(4));
+        assert!(!ic.run_back_to(50));
+        assert_eq!(ic.position(), 0);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut ic = IntComputer::load(DIAGNOSTIC.to_vec());
+        ic.set_history(History::new(2));
+        ic.execute().unwrap();
+        assert_eq!(ic.history().map(|h| h.len()), Some(2));
+        assert!(ic.run_back_to(12));
+        assert_eq!(ic.step_back(), None);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::div_rem;
+
+use std::convert::TryInto;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative
+}
+
+impl Mode {
+    pub fn from_digit(digit: i64) -> Option<Mode> {
+        match digit {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None
+        }
+    }
+
+    pub fn digit(self) -> i64 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2
+        }
+    }
+}
+
+/// Static description of an opcode: its mnemonic, how many parameters it
+/// reads and whether a final parameter is written to.
+#[derive(Debug, PartialEq)]
+pub struct Operation {
+    pub opcode: i64,
+    pub mnemonic: &'static str,
+    pub reads: usize,
+    pub writes: bool
+}
+
+impl Operation {
+    pub fn arity(&self) -> usize {
+        self.reads + self.writes as usize
+    }
+
+    pub fn is_jump(&self) -> bool {
+        self.opcode == 5 || self.opcode == 6
+    }
+}
+
+pub const OPERATIONS: [Operation; 10] = [
+    Operation { opcode: 1, mnemonic: "add", reads: 2, writes: true },
+    Operation { opcode: 2, mnemonic: "mul", reads: 2, writes: true },
+    Operation { opcode: 3, mnemonic: "in", reads: 0, writes: true },
+    Operation { opcode: 4, mnemonic: "out", reads: 1, writes: false },
+    Operation { opcode: 5, mnemonic: "jt", reads: 2, writes: false },
+    Operation { opcode: 6, mnemonic: "jf", reads: 2, writes: false },
+    Operation { opcode: 7, mnemonic: "lt", reads: 2, writes: true },
+    Operation { opcode: 8, mnemonic: "eq", reads: 2, writes: true },
+    Operation { opcode: 9, mnemonic: "arb", reads: 1, writes: false },
+    Operation { opcode: 99, mnemonic: "halt", reads: 0, writes: false },
+];
+
+pub fn operation(opcode: i64) -> Option<&'static Operation> {
+    OPERATIONS.iter().find(|op| op.opcode == opcode)
+}
+
+pub fn operation_by_mnemonic(mnemonic: &str) -> Option<&'static Operation> {
+    OPERATIONS.iter().find(|op| op.mnemonic == mnemonic)
+}
+
+/// Splits a raw instruction word into `(opcode, mode1, mode2, mode3)`.
+pub fn split_opcode(raw_opcode: i64) -> (i64, i64, i64, i64) {
+    let (mode3, raw_opcode) = div_rem(raw_opcode, 10000);
+    let (mode2, raw_opcode) = div_rem(raw_opcode, 1000);
+    let (mode1, raw_opcode) = div_rem(raw_opcode, 100);
+    (raw_opcode, mode1, mode2, mode3)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameter {
+    pub mode: Mode,
+    pub value: i64
+}
+
+impl Parameter {
+    /// The memory cell the parameter refers to, or `None` for an immediate
+    /// value or a negative address.
+    pub fn address(&self, relative_base: i64) -> Option<usize> {
+        let address = match self.mode {
+            Mode::Position => self.value,
+            Mode::Immediate => return None,
+            Mode::Relative => relative_base.checked_add(self.value)?
+        };
+        address.try_into().ok()
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "[rb{}]", self.value),
+            Mode::Relative => write!(f, "[rb+{}]", self.value)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: usize,
+    pub raw: i64,
+    pub operation: &'static Operation,
+    pub parameters: Vec<Parameter>
+}
+
+impl Instruction {
+    /// Decodes the instruction at `address`, or `None` if the word there is
+    /// not a valid instruction (unknown opcode, bad mode, immediate write).
+    /// Like `IntComputer`, only the mode digits of the parameters the
+    /// operation takes are looked at; any others are ignored.
+    pub fn decode(memory: &[i64], address: usize) -> Option<Instruction> {
+        let raw = *memory.get(address)?;
+        let (opcode, mode1, mode2, mode3) = split_opcode(raw);
+        let operation = operation(opcode)?;
+        let digits = [mode1, mode2, mode3];
+        let mut parameters = Vec::new();
+        for (index, digit) in digits.iter().take(operation.arity()).enumerate() {
+            let mode = Mode::from_digit(*digit)?;
+            if operation.writes && index == operation.reads && mode == Mode::Immediate {
+                return None
+            }
+            let value = memory.get(address + index + 1).copied().unwrap_or(0);
+            parameters.push(Parameter { mode, value });
+        }
+        Some(Instruction { address, raw, operation, parameters })
+    }
+
+    pub fn size(&self) -> usize {
+        1 + self.parameters.len()
+    }
+
+    pub fn next(&self) -> usize {
+        self.address + self.size()
+    }
+
+    pub fn inputs(&self) -> &[Parameter] {
+        &self.parameters[..self.operation.reads]
+    }
+
+    pub fn target(&self) -> Option<&Parameter> {
+        if self.operation.writes {
+            self.parameters.last()
+        } else {
+            None
+        }
+    }
+
+    /// Whether the instruction can jump and whether it can go on to the next
+    /// one, judging a jump by its condition when that is immediate.
+    pub fn branches(&self) -> (bool, bool) {
+        if !self.operation.is_jump() {
+            return (false, self.operation.opcode != 99)
+        }
+        match self.parameters[0] {
+            Parameter { mode: Mode::Immediate, value } => {
+                let taken = (value != 0) == (self.operation.opcode == 5);
+                (taken, !taken)
+            },
+            _ => (true, true)
+        }
+    }
+
+    /// The word the assembler would encode this instruction as. It differs
+    /// from `raw` when `raw` has mode digits the operation ignores.
+    pub fn encode(&self) -> i64 {
+        let mut word = self.operation.opcode;
+        let mut scale = 100;
+        for parameter in &self.parameters {
+            word += parameter.mode.digit() * scale;
+            scale *= 10;
+        }
+        word
+    }
+
+    /// The destination of a jump whose target is an immediate operand.
+    pub fn jump_target(&self) -> Option<usize> {
+        if !self.operation.is_jump() {
+            return None
+        }
+        match self.parameters[1] {
+            Parameter { mode: Mode::Immediate, value } if value >= 0 => Some(value as usize),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.operation.mnemonic)?;
+        for (index, parameter) in self.inputs().iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, parameter)?;
+        }
+        if let Some(target) = self.target() {
+            write!(f, " -> {}", target)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let instruction = Instruction::decode(&[1001,9,10,3], 0).unwrap();
+        assert_eq!(instruction.to_string(), "add [9], #10 -> [3]");
+        assert_eq!(instruction.next(), 4);
+        let instruction = Instruction::decode(&[0,0,21107,-3,7,-1], 2).unwrap();
+        assert_eq!(instruction.to_string(), "lt #-3, #7 -> [rb-1]");
+        let instruction = Instruction::decode(&[3,50], 0).unwrap();
+        assert_eq!(instruction.to_string(), "in -> [50]");
+        let instruction = Instruction::decode(&[1105,1,9], 0).unwrap();
+        assert_eq!(instruction.to_string(), "jt #1, #9");
+        assert_eq!(instruction.jump_target(), Some(9));
+        assert_eq!(Instruction::decode(&[99], 0).unwrap().to_string(), "halt");
+        // Mode digits past the parameters are ignored, as when executing.
+        let instruction = Instruction::decode(&[10104,7], 0).unwrap();
+        assert_eq!(instruction.to_string(), "out #7");
+        assert_eq!(instruction.raw, 10104);
+        let mut ic = crate::IntComputer::load(vec![10104,7,99]);
+        ic.execute().unwrap();
+        assert_eq!(ic.read(), Some(7));
+        assert_eq!(Instruction::decode(&[39999], 0).unwrap().to_string(), "halt");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Instruction::decode(&[42], 0), None);
+        assert_eq!(Instruction::decode(&[11101,1,1,0], 0), None);
+        assert_eq!(Instruction::decode(&[301,1,1,0], 0), None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, Sender};
+
+/// Where an `IntComputer` takes its input from. Returning `None` makes the
+/// machine stop with `ExecutionState::WaitingInput`.
+pub trait Input {
+    fn next_input(&mut self) -> Option<i64>;
+}
+
+/// Where an `IntComputer` sends the values it outputs.
+pub trait Output {
+    fn send_output(&mut self, value: i64);
+}
+
+impl Input for VecDeque<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl Output for VecDeque<i64> {
+    fn send_output(&mut self, value: i64) {
+        self.push_back(value)
+    }
+}
+
+impl Output for Vec<i64> {
+    fn send_output(&mut self, value: i64) {
+        self.push(value)
+    }
+}
+
+impl<F: FnMut() -> Option<i64>> Input for F {
+    fn next_input(&mut self) -> Option<i64> {
+        self()
+    }
+}
+
+impl<F: FnMut(i64)> Output for F {
+    fn send_output(&mut self, value: i64) {
+        self(value)
+    }
+}
+
+impl Input for Box<dyn Input> {
+    fn next_input(&mut self) -> Option<i64> {
+        (**self).next_input()
+    }
+}
+
+impl Output for Box<dyn Output> {
+    fn send_output(&mut self, value: i64) {
+        (**self).send_output(value)
+    }
+}
+
+impl Input for Box<dyn Input + Send> {
+    fn next_input(&mut self) -> Option<i64> {
+        (**self).next_input()
+    }
+}
+
+impl Output for Box<dyn Output + Send> {
+    fn send_output(&mut self, value: i64) {
+        (**self).send_output(value)
+    }
+}
+
+/// A queue shared between machines, so one computer's output can be
+/// another's input without copying values across by hand.
+pub type SharedQueue = Rc<RefCell<VecDeque<i64>>>;
+
+pub fn shared_queue() -> SharedQueue {
+    Rc::new(RefCell::new(VecDeque::new()))
+}
+
+impl Input for SharedQueue {
+    fn next_input(&mut self) -> Option<i64> {
+        self.borrow_mut().pop_front()
+    }
+}
+
+impl Output for SharedQueue {
+    fn send_output(&mut self, value: i64) {
+        self.borrow_mut().push_back(value)
+    }
+}
+
+/// Blocks until a value arrives; a disconnected sender reads as no input.
+impl Input for Receiver<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+/// Values sent after the receiver has hung up are dropped.
+impl Output for Sender<i64> {
+    fn send_output(&mut self, value: i64) {
+        let _ = self.send(value);
+    }
+}
+
+/// Feeds the values of an iterator as input.
+pub struct IterInput<T: Iterator<Item = i64>>(pub T);
+
+impl<T: Iterator<Item = i64>> Input for IterInput<T> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.0.next()
+    }
+}
+
+/// Reads one integer per line, e.g. from stdin. End of input, a read error
+/// or a line that is not an integer all read as no input.
+pub struct LineInput<R: BufRead>(pub R);
+
+impl<R: BufRead> Input for LineInput<R> {
+    fn next_input(&mut self) -> Option<i64> {
+        let mut line = String::new();
+        match self.0.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => line.trim().parse().ok()
+        }
+    }
+}
+
+/// Writes one integer per line, e.g. to stdout. Write errors are ignored.
+pub struct LineOutput<W: Write>(pub W);
+
+impl<W: Write> Output for LineOutput<W> {
+    fn send_output(&mut self, value: i64) {
+        let _ = writeln!(self.0, "{}", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecutionState, IntComputer};
+
+    fn doubler() -> Vec<i64> {
+        vec![3,11,1002,11,2,11,4,11,1105,1,0,0]
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut values = vec![3, 2, 1];
+        let mut outputs = Vec::new();
+        let mut ic = IntComputer::with_io(doubler(), || values.pop(), |v| outputs.push(v));
+        assert_eq!(ic.execute(), Ok(&ExecutionState::WaitingInput));
+        drop(ic);
+        assert_eq!(outputs, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn test_iterator_and_vec() {
+        let mut ic = IntComputer::with_io(doubler(), IterInput(1..=3), Vec::new());
+        ic.execute().unwrap();
+        assert_eq!(ic.output(), &vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn test_shared_queue() {
+        let pipe = shared_queue();
+        let mut first = IntComputer::with_io(doubler(), IterInput(vec![5].into_iter()), pipe.clone());
+        let mut second = IntComputer::with_io(doubler(), pipe, Vec::new());
+        first.execute().unwrap();
+        second.execute().unwrap();
+        assert_eq!(second.output(), &vec![20]);
+    }
+
+    #[test]
+    fn test_lines_and_boxes() {
+        let input: Box<dyn Input + Send> = Box::new(IterInput(vec![1, 2].into_iter()));
+        let output: Box<dyn Output + Send> = Box::new(LineOutput(Vec::new()));
+        let mut ic = IntComputer::with_io(doubler(), input, output);
+        assert_eq!(ic.execute(), Ok(&ExecutionState::WaitingInput));
+        let input: Box<dyn Input> = Box::new(shared_queue());
+        let mut ic = IntComputer::with_io(doubler(), input, Vec::new());
+        assert_eq!(ic.execute(), Ok(&ExecutionState::WaitingInput));
+        let mut ic = IntComputer::with_io(doubler(), LineInput("4\n-7\n".as_bytes()), LineOutput(Vec::new()));
+        ic.execute().unwrap();
+        assert_eq!(ic.output().0, b"8\n-14\n");
+    }
+}
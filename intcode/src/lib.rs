@@ -1,170 +1,625 @@
+mod cache;
+mod error;
+pub mod ascii;
+pub mod asm;
+pub mod asynchronous;
+pub mod control_flow;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+pub mod fuzz;
+pub mod history;
+pub mod instruction;
+pub mod io;
+pub mod load;
+pub mod network;
+pub mod profile;
+pub mod selfmod;
+pub mod snapshot;
+pub mod threaded;
+pub mod topology;
+pub mod trace;
+pub mod watch;
+
+use cache::DecodeCache;
+pub use error::{ErrorKind, IntcodeError};
+use history::History;
+use instruction::{operation, split_opcode, Instruction};
+use io::{Input, Output};
+use profile::Profiler;
+use selfmod::CodeTracker;
+use snapshot::Snapshot;
+use trace::{TraceRecord, Tracer};
+use watch::{WatchAction, WatchHit, Watchpoint};
+
 use std::convert::TryInto;
 use std::collections::VecDeque;
 
-#[derive(Debug)]
+/// How far memory may grow, in words, when no limit is set with
+/// `set_memory_limit`.
+pub const MAX_MEMORY: usize = 1 << 24;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExecutionState {
     ReadyToRun,
     Running,
     WaitingInput,
     Halted,
-    FatalError
+    /// `execute_for` or `execute_until` used up its instruction budget.
+    BudgetExhausted,
+    /// A watchpoint with `WatchAction::Pause` fired.
+    Paused,
+    FatalError(IntcodeError)
 }
 
-pub struct IntComputer {
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old: i64,
+    pub new: i64
+}
+
+/// An intcode machine. Input and output default to in-memory queues fed
+/// with `write` and drained with `read`; `with_io` plugs in any other
+/// `Input` and `Output`.
+pub struct IntComputer<I: Input = VecDeque<i64>, O: Output = VecDeque<i64>> {
     state: Vec<i64>,
     position: usize,
-    input: VecDeque<i64>,
-    output: VecDeque<i64>,
-    execution_state: ExecutionState
+    relative_base: i64,
+    memory_limit: Option<usize>,
+    input: I,
+    output: O,
+    execution_state: ExecutionState,
+    last_write: Option<MemoryWrite>,
+    cycles: u64,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    watchpoints: Vec<(Watchpoint, WatchAction)>,
+    watch_hit: Option<WatchHit>,
+    history: Option<History>,
+    code_tracker: Option<CodeTracker>,
+    cache: Option<DecodeCache>
 }
 
-impl IntComputer {
-    pub fn execute(&mut self) -> &ExecutionState {
-        loop {
-            match self.execute_step() {
-                ExecutionState::Running =>
-                    continue,
-                _ =>
-                    break,
-            }
+/// Clones the machine state, profiler, history and code tracker. An
+/// attached tracer and watchpoints stay with the original.
+impl<I: Input + Clone, O: Output + Clone> Clone for IntComputer<I, O> {
+    fn clone(&self) -> IntComputer<I, O> {
+        IntComputer {
+            state: self.state.clone(),
+            position: self.position,
+            relative_base: self.relative_base,
+            memory_limit: self.memory_limit,
+            input: self.input.clone(),
+            output: self.output.clone(),
+            execution_state: self.execution_state.clone(),
+            last_write: self.last_write.clone(),
+            cycles: self.cycles,
+            tracer: None,
+            profiler: self.profiler.clone(),
+            watchpoints: Vec::new(),
+            watch_hit: self.watch_hit,
+            history: self.history.clone(),
+            code_tracker: self.code_tracker.clone(),
+            cache: self.cache.clone()
         }
-        &self.execution_state
     }
+}
 
-    pub fn execute_step(&mut self) -> &ExecutionState {
-        match self.execution_state {
-            ExecutionState::Halted | ExecutionState::FatalError => {
-                return &self.execution_state
+impl<I: Input, O: Output> IntComputer<I, O> {
+    pub fn with_io(state: Vec<i64>, input: I, output: O) -> IntComputer<I, O> {
+        IntComputer {
+            state,
+            position : 0,
+            relative_base: 0,
+            memory_limit: None,
+            input,
+            output,
+            execution_state: ExecutionState::ReadyToRun,
+            last_write: None,
+            cycles: 0,
+            tracer: None,
+            profiler: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            history: None,
+            code_tracker: None,
+            cache: None
+        }
+    }
+
+    pub fn execute(&mut self) -> Result<&ExecutionState, IntcodeError> {
+        if !self.hooked() {
+            return self.run_unhooked(u64::MAX)
+        }
+        while let ExecutionState::Running = self.execute_step()? {}
+        Ok(&self.execution_state)
+    }
+
+    /// Like `execute`, but stops with `BudgetExhausted` after `budget`
+    /// instructions.
+    pub fn execute_for(&mut self, budget: u64) -> Result<&ExecutionState, IntcodeError> {
+        self.execute_until(self.cycles.saturating_add(budget))
+    }
+
+    /// Like `execute`, but stops with `BudgetExhausted` once the cycle
+    /// counter reaches `cycles`.
+    pub fn execute_until(&mut self, cycles: u64) -> Result<&ExecutionState, IntcodeError> {
+        if !self.hooked() {
+            return self.run_unhooked(cycles)
+        }
+        while self.cycles < cycles {
+            if *self.execute_step()? != ExecutionState::Running {
+                return Ok(&self.execution_state)
             }
+        }
+        match &self.execution_state {
+            ExecutionState::FatalError(error) => return Err(error.clone()),
+            ExecutionState::Halted => (),
+            _ => self.execution_state = ExecutionState::BudgetExhausted
+        }
+        Ok(&self.execution_state)
+    }
+
+    pub fn execute_step(&mut self) -> Result<&ExecutionState, IntcodeError> {
+        match &self.execution_state {
+            ExecutionState::Halted => {
+                return Ok(&self.execution_state)
+            },
+            ExecutionState::FatalError(error) => {
+                return Err(error.clone())
+            },
             _ => ()
         }
 
+        let undo = self.start_record();
+        self.execution_state = ExecutionState::Running;
+        self.last_write = None;
+
+        let position = self.position;
+        let instruction = self.fetch(position);
+        let operands = self.tracer.as_ref().map(|_| self.operand_values());
+        let reads = if self.profiler.is_some() || !self.watchpoints.is_empty() {
+            self.operand_addresses().into_iter().map(|address| (address, self.fetch(address))).collect()
+        } else {
+            Vec::new()
+        };
+
+        if let Err(error) = self.step() {
+            self.execution_state = ExecutionState::FatalError(error.clone());
+            return Err(error)
+        }
+        if self.execution_state != ExecutionState::WaitingInput {
+            self.cycles += 1;
+            if let Some(record) = undo {
+                self.finish_record(record, instruction);
+            }
+            if let Some(profiler) = self.profiler.as_mut() {
+                let addresses: Vec<usize> = reads.iter().map(|(address, _)| *address).collect();
+                let write = self.last_write.as_ref().map(|write| write.address);
+                profiler.record(position, instruction, &addresses, write, self.position);
+            }
+            if let Some(tracker) = self.code_tracker.as_mut() {
+                tracker.record(position, instruction, self.last_write.as_ref());
+            }
+            self.check_watchpoints(position, &reads);
+        }
+
+        if let (Some(tracer), Some(operands)) = (self.tracer.as_mut(), operands) {
+            if self.execution_state != ExecutionState::WaitingInput {
+                tracer.record(&TraceRecord {
+                    position,
+                    instruction,
+                    operands,
+                    write: self.last_write.clone()
+                });
+            }
+        }
+        Ok(&self.execution_state)
+    }
+
+    /// Whether anything is observing individual steps.
+    fn hooked(&self) -> bool {
+        self.tracer.is_some() || self.profiler.is_some() || !self.watchpoints.is_empty() || self.history.is_some()
+            || self.code_tracker.is_some()
+    }
+
+    /// `execute_until` without the per-step hooks, using the decode cache
+    /// when it is on.
+    fn run_unhooked(&mut self, cycles: u64) -> Result<&ExecutionState, IntcodeError> {
+        match &self.execution_state {
+            ExecutionState::Halted => return Ok(&self.execution_state),
+            ExecutionState::FatalError(error) => return Err(error.clone()),
+            _ => ()
+        }
         self.execution_state = ExecutionState::Running;
+        if self.cache.is_some() {
+            if let Err(error) = self.run_cached(cycles) {
+                self.execution_state = ExecutionState::FatalError(error.clone());
+                return Err(error)
+            }
+            if self.execution_state != ExecutionState::Running {
+                return Ok(&self.execution_state)
+            }
+        }
+        while self.cycles < cycles {
+            if let Err(error) = self.step() {
+                self.execution_state = ExecutionState::FatalError(error.clone());
+                return Err(error)
+            }
+            match self.execution_state {
+                ExecutionState::Running => self.cycles += 1,
+                ExecutionState::Halted => {
+                    self.cycles += 1;
+                    return Ok(&self.execution_state)
+                },
+                _ => return Ok(&self.execution_state)
+            }
+        }
+        self.execution_state = ExecutionState::BudgetExhausted;
+        Ok(&self.execution_state)
+    }
 
-        let (opcode, mode1, mode2, _mode3) = self.opcode();
+    fn step(&mut self) -> Result<(), IntcodeError> {
+        let (opcode, mode1, mode2, mode3) = self.opcode();
         match opcode {
             1 => {
-                let arg1 = self.argument_value(mode1, 1);
-                let arg2 = self.argument_value(mode2, 2);
-                let arg3:usize = self.argument_value(1, 3).try_into().unwrap();
-                self.state[arg3] = arg1 + arg2;
+                let arg1 = self.argument_value(mode1, 1)?;
+                let arg2 = self.argument_value(mode2, 2)?;
+                let arg3 = self.argument_address(mode3, 3)?;
+                let sum = arg1.checked_add(arg2)
+                    .ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                self.store(arg3, sum)?;
                 self.position += 4;
             },
             2 => {
-                let arg1 = self.argument_value(mode1, 1);
-                let arg2 = self.argument_value(mode2, 2);
-                let arg3:usize = self.argument_value(1, 3).try_into().unwrap();
-                self.state[arg3] = arg1 * arg2;
+                let arg1 = self.argument_value(mode1, 1)?;
+                let arg2 = self.argument_value(mode2, 2)?;
+                let arg3 = self.argument_address(mode3, 3)?;
+                let product = arg1.checked_mul(arg2)
+                    .ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                self.store(arg3, product)?;
                 self.position += 4;
             },
             3 => {
-                let arg1:usize = self.argument_value(1, 1).try_into().unwrap();
-                if let Some(i) = self.input.pop_front() {
-                    self.state[arg1] = i;
+                let arg1 = self.argument_address(mode1, 1)?;
+                if let Some(i) = self.input.next_input() {
+                    self.store(arg1, i)?;
                     self.position += 2;
                 } else {
                     self.execution_state = ExecutionState::WaitingInput;
                 }
             },
             4 => {
-                let arg1 = self.argument_value(mode1, 1);
-                self.output.push_back(arg1);
+                let arg1 = self.argument_value(mode1, 1)?;
+                self.output.send_output(arg1);
                 self.position += 2;
             },
             5 => {
-                let arg1 = self.argument_value(mode1, 1);
-                let arg2 = self.argument_value(mode2, 2);
+                let arg1 = self.argument_value(mode1, 1)?;
+                let arg2 = self.argument_value(mode2, 2)?;
                 if arg1 != 0 {
-                    self.position = arg2.try_into().unwrap();
+                    self.position = self.address(arg2)?;
                 } else {
                     self.position += 3;
                 }
             },
             6 => {
-                let arg1 = self.argument_value(mode1, 1);
-                let arg2 = self.argument_value(mode2, 2);
+                let arg1 = self.argument_value(mode1, 1)?;
+                let arg2 = self.argument_value(mode2, 2)?;
                 if arg1 == 0 {
-                    self.position = arg2.try_into().unwrap();
+                    self.position = self.address(arg2)?;
                 } else {
                     self.position += 3;
                 }
             },
             7 => {
-                let arg1 = self.argument_value(mode1, 1);
-                let arg2 = self.argument_value(mode2, 2);
-                let arg3:usize = self.argument_value(1, 3).try_into().unwrap();
-                if arg1 < arg2 {
-                    self.state[arg3] = 1
-                } else {
-                    self.state[arg3] = 0
-                }
+                let arg1 = self.argument_value(mode1, 1)?;
+                let arg2 = self.argument_value(mode2, 2)?;
+                let arg3 = self.argument_address(mode3, 3)?;
+                self.store(arg3, (arg1 < arg2) as i64)?;
                 self.position += 4;
             },
             8 => {
-                let arg1 = self.argument_value(mode1, 1);
-                let arg2 = self.argument_value(mode2, 2);
-                let arg3:usize = self.argument_value(1, 3).try_into().unwrap();
-                if arg1 == arg2 {
-                    self.state[arg3] = 1
-                } else {
-                    self.state[arg3] = 0
-                }
+                let arg1 = self.argument_value(mode1, 1)?;
+                let arg2 = self.argument_value(mode2, 2)?;
+                let arg3 = self.argument_address(mode3, 3)?;
+                self.store(arg3, (arg1 == arg2) as i64)?;
                 self.position += 4;
             },
+            9 => {
+                let arg1 = self.argument_value(mode1, 1)?;
+                self.relative_base = self.relative_base.checked_add(arg1)
+                    .ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                self.position += 2;
+            },
             99 => {
                 self.execution_state = ExecutionState::Halted
             },
             _ => {
-                self.execution_state = ExecutionState::FatalError
+                return Err(self.fault(ErrorKind::UnknownOpcode(opcode)))
             }
         }
-        &self.execution_state
+        Ok(())
     }
 
-    pub fn load(state: Vec<i64>) -> IntComputer {
-        IntComputer {
-            state : state,
-            position : 0,
-            input: VecDeque::new(),
-            output: VecDeque::new(),
-            execution_state: ExecutionState::ReadyToRun
+    pub fn value(&self, position: usize) -> i64 {
+        self.fetch(position)
+    }
+
+    /// Writes `value` at `position`, growing memory as needed. Growing to
+    /// or past the memory limit, or `MAX_MEMORY` without one, fails with
+    /// `ErrorKind::AddressOutOfRange`.
+    pub fn set(&mut self, position: usize, value: i64) -> Result<(), IntcodeError> {
+        if position >= self.state.len() {
+            let limit = self.memory_limit.unwrap_or(MAX_MEMORY);
+            if position >= limit {
+                return Err(self.fault(ErrorKind::AddressOutOfRange { address: position, limit }))
+            }
+            self.state.resize(position + 1, 0);
+        }
+        self.state[position] = value;
+        if let Some(cache) = self.cache.as_mut() {
+            cache.invalidate(position);
         }
+        Ok(())
     }
 
-    pub fn value(self, position: usize) -> i64 {
-        self.state[position]
+    /// Turns the decode cache on or off (the default). Turning it on decodes
+    /// memory as it is now, once, and `execute` then runs from the decoded
+    /// instructions until a write touches their words. Clones share the
+    /// decoded memory. It only pays off for long-running loops; machines
+    /// that run a few dozen instructions between inputs are no faster.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache = if enabled { Some(DecodeCache::new(&self.state)) } else { None };
     }
 
-    pub fn set(&mut self, position: usize, value: i64) {
-        self.state[position] = value
+    /// Caps how far a running program may grow memory; writes at or past
+    /// `limit` fail with `ErrorKind::AddressOutOfRange`. Without a limit,
+    /// memory can grow up to `MAX_MEMORY` words.
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = Some(limit);
     }
 
-    pub fn write(&mut self, value: i64) {
-        self.input.push_back(value);
+    pub fn input(&self) -> &I {
+        &self.input
     }
 
-    pub fn read(&mut self) -> Option<i64>{
-        self.output.pop_front()
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    pub fn output(&self) -> &O {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut O {
+        &mut self.output
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /// Number of instructions executed so far, including the final halt.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn state(&self) -> &ExecutionState {
+        &self.execution_state
+    }
+
+    pub fn memory(&self) -> &[i64] {
+        &self.state
+    }
+
+    /// Records every instruction executed from now on to `tracer`.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Counts executions, reads and writes per address from now on.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Fires `action` whenever an instruction accesses memory the way
+    /// `watchpoint` describes.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint, action: WatchAction) {
+        self.watchpoints.push((watchpoint, action));
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// The watchpoint hit that last paused the machine.
+    pub fn watch_hit(&self) -> Option<&WatchHit> {
+        self.watch_hit.as_ref()
+    }
+
+    /// Logs executed instructions so they can be undone with `step_back`.
+    pub fn set_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn take_history(&mut self) -> Option<History> {
+        self.history.take()
+    }
+
+    /// Records writes to addresses that have already run as code.
+    pub fn set_code_tracker(&mut self, tracker: CodeTracker) {
+        self.code_tracker = Some(tracker);
+    }
+
+    pub fn code_tracker(&self) -> Option<&CodeTracker> {
+        self.code_tracker.as_ref()
+    }
+
+    pub fn take_code_tracker(&mut self) -> Option<CodeTracker> {
+        self.code_tracker.take()
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Decodes the instruction at the instruction pointer, if it is valid.
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        Instruction::decode(&self.state, self.position)
     }
 
     fn opcode(&self) -> (i64, i64, i64, i64) {
-        let raw_opcode = self.state[self.position];
-        let (mode3, raw_opcode) = div_rem(raw_opcode, 10000);
-        let (mode2, raw_opcode) = div_rem(raw_opcode, 1000);
-        let (mode1, raw_opcode) = div_rem(raw_opcode, 100);
-        (raw_opcode, mode1, mode2, mode3)
+        split_opcode(self.fetch(self.position))
+    }
+
+    /// Resolved values of the parameters the current instruction reads.
+    fn operand_values(&self) -> Vec<i64> {
+        let (opcode, mode1, mode2, mode3) = self.opcode();
+        let reads = operation(opcode).map_or(0, |op| op.reads);
+        [mode1, mode2, mode3].iter().take(reads).enumerate()
+            .map(|(index, mode)| self.argument_value(*mode, index + 1).unwrap_or(0))
+            .collect()
+    }
+
+    fn check_watchpoints(&mut self, position: usize, reads: &[(usize, i64)]) {
+        let write = self.last_write.as_ref().map(|write| (write.address, write.new));
+        let mut paused = None;
+        for (watchpoint, action) in self.watchpoints.iter_mut() {
+            for hit in watch::hits(watchpoint, position, reads, write) {
+                match action {
+                    WatchAction::Pause => paused = paused.or(Some(hit)),
+                    WatchAction::Callback(callback) => callback(&hit)
+                }
+            }
+        }
+        if paused.is_some() && self.execution_state == ExecutionState::Running {
+            self.execution_state = ExecutionState::Paused;
+            self.watch_hit = paused;
+        }
     }
 
-    fn argument_value(&self, mode:i64, argument: usize) -> i64 {
+    fn operand_addresses(&self) -> Vec<usize> {
+        let (opcode, mode1, mode2, mode3) = self.opcode();
+        let reads = operation(opcode).map_or(0, |op| op.reads);
+        [mode1, mode2, mode3].iter().take(reads).enumerate()
+            .filter(|(_, mode)| **mode != 1)
+            .filter_map(|(index, mode)| self.argument_address(*mode, index + 1).ok())
+            .collect()
+    }
+
+    fn argument_value(&self, mode:i64, argument: usize) -> Result<i64, IntcodeError> {
         match mode {
             1 => {
-                self.state[self.position + argument]
+                Ok(self.fetch(self.position + argument))
             },
             _ => {
-                let position:usize = self.state[self.position + argument].try_into().unwrap();
-                self.state[position]
+                let position = self.argument_address(mode, argument)?;
+                Ok(self.fetch(position))
+            }
+        }
+    }
+
+    fn argument_address(&self, mode:i64, argument: usize) -> Result<usize, IntcodeError> {
+        let raw = self.fetch(self.position + argument);
+        match mode {
+            0 => self.address(raw),
+            1 => Err(self.fault(ErrorKind::ImmediateWrite { parameter: argument })),
+            2 => {
+                let address = self.relative_base.checked_add(raw)
+                    .ok_or_else(|| self.fault(ErrorKind::ArithmeticOverflow))?;
+                self.address(address)
+            },
+            _ => Err(self.fault(ErrorKind::InvalidMode { parameter: argument, mode }))
+        }
+    }
+
+    fn address(&self, raw: i64) -> Result<usize, IntcodeError> {
+        raw.try_into().map_err(|_| self.fault(ErrorKind::NegativeAddress(raw)))
+    }
+
+    fn fault(&self, kind: ErrorKind) -> IntcodeError {
+        IntcodeError {
+            position: self.position,
+            instruction: self.fetch(self.position),
+            kind
+        }
+    }
+
+    fn fetch(&self, position: usize) -> i64 {
+        self.state.get(position).copied().unwrap_or(0)
+    }
+
+    fn store(&mut self, position: usize, value: i64) -> Result<(), IntcodeError> {
+        if let Some(limit) = self.memory_limit {
+            if position >= limit {
+                return Err(self.fault(ErrorKind::AddressOutOfRange { address: position, limit }))
             }
         }
+        let old = self.fetch(position);
+        self.set(position, value)?;
+        self.last_write = Some(MemoryWrite { address: position, old, new: value });
+        Ok(())
+    }
+}
+
+impl IntComputer {
+    pub fn load(state: Vec<i64>) -> IntComputer {
+        IntComputer::with_io(state, VecDeque::new(), VecDeque::new())
+    }
+
+    pub fn restore(snapshot: &Snapshot) -> IntComputer {
+        let mut ic = IntComputer::load(snapshot.memory.clone());
+        ic.position = snapshot.position;
+        ic.relative_base = snapshot.relative_base;
+        ic.cycles = snapshot.cycles;
+        ic.memory_limit = snapshot.memory_limit;
+        ic.input = snapshot.input.iter().copied().collect();
+        ic.output = snapshot.output.iter().copied().collect();
+        ic.execution_state = snapshot.execution_state.clone();
+        ic
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.state.clone(),
+            position: self.position,
+            relative_base: self.relative_base,
+            cycles: self.cycles,
+            memory_limit: self.memory_limit,
+            input: self.input.iter().copied().collect(),
+            output: self.output.iter().copied().collect(),
+            execution_state: self.execution_state.clone()
+        }
+    }
+}
+
+impl<O: Output> IntComputer<VecDeque<i64>, O> {
+    pub fn write(&mut self, value: i64) {
+        self.input.push_back(value);
+    }
+}
+
+impl<I: Input> IntComputer<I, VecDeque<i64>> {
+    pub fn read(&mut self) -> Option<i64>{
+        self.output.pop_front()
     }
 }
 
@@ -181,7 +636,7 @@ mod tests {
     #[test]
     fn test_simple() {
         let mut ic = IntComputer::load(vec![1,9,10,3,2,3,11,0,99,30,40,50]);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.value(0), 3500);
     }
 
@@ -194,14 +649,14 @@ mod tests {
     #[test]
     fn test_argmode() {
         let mut ic = IntComputer::load(vec![1002,4,3,4,33]);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.value(4), 99)
     }
 
     #[test]
     fn test_negative() {
         let mut ic = IntComputer::load(vec![1101,100,-1,4,0]);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.value(4), 99)
     }
 
@@ -209,7 +664,7 @@ mod tests {
     fn input_output() {
         let mut ic = IntComputer::load(vec![3,0,4,0,99]);
         ic.write(145);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(145));
         assert_eq!(ic.read(), None)
     }
@@ -218,35 +673,35 @@ mod tests {
     fn test_equal_less_than() {
         let mut ic = IntComputer::load(vec![3,9,8,9,10,9,4,9,99,-1,8]);
         ic.write(8);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1));
         let mut ic = IntComputer::load(vec![3,9,8,9,10,9,4,9,99,-1,8]);
         ic.write(7);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(0));
         let mut ic = IntComputer::load(vec![3,9,7,9,10,9,4,9,99,-1,8]);
         ic.write(9);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(0));
         let mut ic = IntComputer::load(vec![3,9,7,9,10,9,4,9,99,-1,8]);
         ic.write(7);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1));
         let mut ic = IntComputer::load(vec![3,3,1108,-1,8,3,4,3,99]);
         ic.write(8);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1));
         let mut ic = IntComputer::load(vec![3,3,1108,-1,8,3,4,3,99]);
         ic.write(7);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(0));
         let mut ic = IntComputer::load(vec![3,3,1107,-1,8,3,4,3,99]);
         ic.write(8);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(0));
         let mut ic = IntComputer::load(vec![3,3,1107,-1,8,3,4,3,99]);
         ic.write(7);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1));
     }
 
@@ -254,19 +709,19 @@ mod tests {
     fn test_jumps() {
         let mut ic = IntComputer::load(vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9]);
         ic.write(0);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(0));
         let mut ic = IntComputer::load(vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9]);
         ic.write(100);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1));
         let mut ic = IntComputer::load(vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1]);
         ic.write(0);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(0));
         let mut ic = IntComputer::load(vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1]);
         ic.write(100);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1));
     }
 
@@ -277,21 +732,145 @@ mod tests {
                  1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
                  999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99]);
         ic.write(7);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(999));
         let mut ic = IntComputer::load(
             vec![3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
                  1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
                  999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99]);
         ic.write(8);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1000));
         let mut ic = IntComputer::load(
             vec![3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
                  1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
                  999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99]);
         ic.write(9);
-        ic.execute();
+        ic.execute().unwrap();
         assert_eq!(ic.read(), Some(1001));
     }
+
+    #[test]
+    fn test_relative_base() {
+        let mut ic = IntComputer::load(vec![109,19,204,-34,99]);
+        ic.relative_base = 2000;
+        ic.set(1985, 42).unwrap();
+        ic.execute().unwrap();
+        assert_eq!(ic.relative_base, 2019);
+        assert_eq!(ic.read(), Some(42));
+    }
+
+    #[test]
+    fn test_relative_write() {
+        let mut ic = IntComputer::load(vec![109,10,203,-1,204,-1,99,0,0,0]);
+        ic.write(77);
+        ic.execute().unwrap();
+        assert_eq!(ic.value(9), 77);
+    }
+
+    #[test]
+    fn test_quine() {
+        let program = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        let mut ic = IntComputer::load(program.clone());
+        ic.execute().unwrap();
+        let mut output = Vec::new();
+        while let Some(o) = ic.read() {
+            output.push(o);
+        }
+        assert_eq!(output, program);
+    }
+
+    #[test]
+    fn test_memory_growth() {
+        let mut ic = IntComputer::load(vec![1101,20,22,1000,4,1000,4,2000,99]);
+        ic.execute().unwrap();
+        assert_eq!(ic.read(), Some(42));
+        assert_eq!(ic.read(), Some(0));
+        assert_eq!(ic.value(1000), 42);
+        assert_eq!(ic.value(5000), 0);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut ic = IntComputer::load(vec![1101,20,22,1000,99]);
+        ic.set_memory_limit(1000);
+        let error = ic.execute().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::AddressOutOfRange { address: 1000, limit: 1000 });
+        let mut ic = IntComputer::load(vec![1101,20,22,999,99]);
+        ic.set_memory_limit(1000);
+        ic.execute().unwrap();
+        assert_eq!(ic.value(999), 42);
+        let mut ic = IntComputer::load(vec![1101,1,1,4611686018427387903,99]);
+        let error = ic.execute().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::AddressOutOfRange { address: 4611686018427387903, limit: MAX_MEMORY });
+        assert_eq!(ic.memory().len(), 5);
+        assert_eq!(ic.set(MAX_MEMORY, 1).unwrap_err().kind, ErrorKind::AddressOutOfRange {
+            address: MAX_MEMORY,
+            limit: MAX_MEMORY
+        });
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut ic = IntComputer::load(vec![1101,40,2,4,0,99]);
+        let error = ic.execute().unwrap_err();
+        assert_eq!(error, IntcodeError { position: 4, instruction: 42, kind: ErrorKind::UnknownOpcode(42) });
+        assert_eq!(ic.execute(), Err(error.clone()));
+        assert_eq!(ic.execution_state, ExecutionState::FatalError(error));
+    }
+
+    #[test]
+    fn test_faults() {
+        let mut ic = IntComputer::load(vec![1,-1,0,0,99]);
+        assert_eq!(ic.execute().unwrap_err().kind, ErrorKind::NegativeAddress(-1));
+        let mut ic = IntComputer::load(vec![11101,1,1,0,99]);
+        assert_eq!(ic.execute().unwrap_err().kind, ErrorKind::ImmediateWrite { parameter: 3 });
+        let mut ic = IntComputer::load(vec![301,0,0,0,99]);
+        assert_eq!(ic.execute().unwrap_err().kind, ErrorKind::InvalidMode { parameter: 1, mode: 3 });
+        let mut ic = IntComputer::load(vec![1102,i64::MAX,2,0,99]);
+        assert_eq!(ic.execute().unwrap_err().kind, ErrorKind::ArithmeticOverflow);
+        let mut ic = IntComputer::load(vec![1106,0,-5]);
+        assert_eq!(ic.execute().unwrap_err().kind, ErrorKind::NegativeAddress(-5));
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut ic = IntComputer::load(vec![1105,1,0]);
+        assert_eq!(ic.execute_for(10), Ok(&ExecutionState::BudgetExhausted));
+        assert_eq!(ic.cycles(), 10);
+        assert_eq!(ic.execute_until(25), Ok(&ExecutionState::BudgetExhausted));
+        assert_eq!(ic.cycles(), 25);
+        let mut ic = IntComputer::load(vec![3,7,4,7,99,0,0,0]);
+        assert_eq!(ic.execute_for(10), Ok(&ExecutionState::WaitingInput));
+        assert_eq!(ic.cycles(), 0);
+        ic.write(5);
+        assert_eq!(ic.execute_for(2), Ok(&ExecutionState::BudgetExhausted));
+        assert_eq!(ic.execute_for(1), Ok(&ExecutionState::Halted));
+        assert_eq!(ic.execute_for(1), Ok(&ExecutionState::Halted));
+        assert_eq!(ic.cycles(), 3);
+        assert_eq!(ic.read(), Some(5));
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut ic = IntComputer::load(vec![3,9,8,9,10,9,4,9,99,-1,8]);
+        ic.execute().unwrap();
+        let mut branch = ic.clone();
+        ic.write(8);
+        ic.execute().unwrap();
+        branch.write(7);
+        branch.execute().unwrap();
+        assert_eq!(ic.read(), Some(1));
+        assert_eq!(branch.read(), Some(0));
+    }
+
+    #[test]
+    fn test_large_numbers() {
+        let mut ic = IntComputer::load(vec![104,1125899906842624,99]);
+        ic.execute().unwrap();
+        assert_eq!(ic.read(), Some(1125899906842624));
+        let mut ic = IntComputer::load(vec![1102,34915192,34915192,7,4,7,99,0]);
+        ic.execute().unwrap();
+        assert_eq!(ic.read(), Some(1219070632396864));
+    }
 }
@@ -0,0 +1,76 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// A value that is not an integer, with its 0-based index among the
+    /// comma-separated values.
+    Parse { index: usize, token: String }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Parse { index, token } => write!(f, "invalid value {:?} at index {}", token, index)
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+/// Parses a comma-separated program. Whitespace around values, line
+/// breaks and a trailing comma are ignored.
+pub fn parse(source: &str) -> Result<Vec<i64>, LoadError> {
+    let source = source.trim();
+    let source = source.strip_suffix(',').unwrap_or(source);
+    if source.is_empty() {
+        return Ok(Vec::new())
+    }
+    source.split(',').enumerate().map(|(index, token)| {
+        let token = token.trim();
+        token.parse().map_err(|_| LoadError::Parse { index, token: token.to_string() })
+    }).collect()
+}
+
+pub fn read<R: Read>(mut reader: R) -> Result<Vec<i64>, LoadError> {
+    let mut source = String::new();
+    reader.read_to_string(&mut source)?;
+    parse(&source)
+}
+
+pub fn file<P: AsRef<Path>>(path: P) -> Result<Vec<i64>, LoadError> {
+    read(File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("1,9, 10,3,\n2,3,11,0,99,-30\n").unwrap(), vec![1,9,10,3,2,3,11,0,99,-30]);
+        assert_eq!(parse("1,2,\r\n").unwrap(), vec![1,2]);
+        assert_eq!(parse(" \n").unwrap(), Vec::<i64>::new());
+        assert_eq!(read("104,7,99".as_bytes()).unwrap(), vec![104,7,99]);
+    }
+
+    #[test]
+    fn test_errors() {
+        match parse("1,2,x3,4") {
+            Err(LoadError::Parse { index, token }) => assert_eq!((index, token.as_str()), (2, "x3")),
+            other => panic!("unexpected {:?}", other)
+        }
+        assert_eq!(parse("1,,2").unwrap_err().to_string(), "invalid value \"\" at index 1");
+        assert!(matches!(file("does/not/exist.txt"), Err(LoadError::Io(_))));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{ExecutionState, IntComputer, IntcodeError};
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Packet {
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkEvent {
+    /// A machine sent a packet to another machine.
+    Delivered { source: usize, packet: Packet },
+    /// A machine sent a packet to the NAT, which keeps only the latest one.
+    ToNat { source: usize, packet: Packet },
+    /// A machine sent a packet to an address nobody listens on.
+    Undeliverable { source: usize, packet: Packet },
+    /// The network went idle and the NAT resent its last packet to address 0.
+    NatInjected(Packet)
+}
+
+struct Nat {
+    address: i64,
+    last: Option<Packet>
+}
+
+/// Boots `N` copies of a program with addresses `0..N` and routes the
+/// `(destination, x, y)` triples they output to each other's input.
+/// A machine asking for input with no packet queued is given `-1`.
+pub struct Network {
+    machines: Vec<IntComputer>,
+    queues: Vec<VecDeque<(i64, i64)>>,
+    partial: Vec<Vec<i64>>,
+    nat: Option<Nat>,
+    idle_threshold: usize,
+    idle_rounds: usize
+}
+
+impl Network {
+    pub fn new(program: &[i64], size: usize) -> Network {
+        let machines = (0..size).map(|address| {
+            let mut ic = IntComputer::load(program.to_vec());
+            ic.write(address as i64);
+            ic
+        }).collect();
+        Network {
+            machines,
+            queues: vec![VecDeque::new(); size],
+            partial: vec![Vec::new(); size],
+            nat: None,
+            idle_threshold: 2,
+            idle_rounds: 0
+        }
+    }
+
+    /// Installs a NAT listening on `address`. When the network has been idle
+    /// for `idle_threshold` rounds, it sends its last packet to machine 0.
+    pub fn set_nat(&mut self, address: i64) {
+        self.nat = Some(Nat { address, last: None });
+    }
+
+    /// How many consecutive rounds with empty queues and no packets sent
+    /// count as idle. Defaults to 2.
+    pub fn set_idle_threshold(&mut self, rounds: usize) {
+        self.idle_threshold = rounds.max(1);
+    }
+
+    pub fn machines(&self) -> &[IntComputer] {
+        &self.machines
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle_rounds >= self.idle_threshold
+    }
+
+    /// Gives every machine one turn: its queued packets (or `-1`) are fed
+    /// in and it runs until it needs more input. Returns what happened.
+    pub fn step(&mut self) -> Result<Vec<NetworkEvent>, IntcodeError> {
+        let mut events = Vec::new();
+        let mut idle = true;
+        for address in 0..self.machines.len() {
+            let machine = &mut self.machines[address];
+            if *machine.state() == ExecutionState::Halted {
+                continue
+            }
+            if self.queues[address].is_empty() {
+                machine.write(-1);
+            } else {
+                idle = false;
+                while let Some((x, y)) = self.queues[address].pop_front() {
+                    machine.write(x);
+                    machine.write(y);
+                }
+            }
+            machine.execute()?;
+            while let Some(value) = machine.read() {
+                self.partial[address].push(value);
+            }
+            let triples = self.partial[address].len() / 3;
+            let sent: Vec<i64> = self.partial[address].drain(..triples * 3).collect();
+            for triple in sent.chunks(3) {
+                idle = false;
+                let packet = Packet { destination: triple[0], x: triple[1], y: triple[2] };
+                events.push(self.route(address, packet));
+            }
+        }
+
+        if idle {
+            self.idle_rounds += 1;
+        } else {
+            self.idle_rounds = 0;
+        }
+        if self.is_idle() {
+            if let Some(packet) = self.nat.as_ref().and_then(|nat| nat.last) {
+                self.queues[0].push_back((packet.x, packet.y));
+                self.idle_rounds = 0;
+                events.push(NetworkEvent::NatInjected(packet));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Runs rounds until `stop` accepts an event, which is returned, or
+    /// every machine has halted.
+    pub fn run_until<F>(&mut self, mut stop: F) -> Result<Option<NetworkEvent>, IntcodeError>
+        where F: FnMut(&NetworkEvent) -> bool {
+        loop {
+            for event in self.step()? {
+                if stop(&event) {
+                    return Ok(Some(event))
+                }
+            }
+            if self.machines.iter().all(|m| *m.state() == ExecutionState::Halted) {
+                return Ok(None)
+            }
+        }
+    }
+
+    fn route(&mut self, source: usize, packet: Packet) -> NetworkEvent {
+        if let Some(nat) = self.nat.as_mut() {
+            if packet.destination == nat.address {
+                nat.last = Some(packet);
+                return NetworkEvent::ToNat { source, packet }
+            }
+        }
+        if packet.destination >= 0 && (packet.destination as usize) < self.queues.len() {
+            self.queues[packet.destination as usize].push_back((packet.x, packet.y));
+            NetworkEvent::Delivered { source, packet }
+        } else {
+            NetworkEvent::Undeliverable { source, packet }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    // Machine 0 sends (7, 8) to machine 1; every machine forwards whatever
+    // it receives to address 255.
+    const RELAY: &str = "
+                in -> [addr]
+                jt [addr], #listen
+                out #1
+                out #7
+                out #8
+        listen: in -> [x]
+                eq [x], #-1 -> [tmp]
+                jt [tmp], #listen
+                in -> [y]
+                out #255
+                out [x]
+                out [y]
+                jt #1, #listen
+        addr:   data 0
+        x:      data 0
+        y:      data 0
+        tmp:    data 0
+    ";
+
+    #[test]
+    fn test_routing() {
+        let program = assemble(RELAY).unwrap();
+        let mut network = Network::new(&program, 3);
+        assert_eq!(network.step().unwrap(), vec![
+            NetworkEvent::Delivered { source: 0, packet: Packet { destination: 1, x: 7, y: 8 } },
+            NetworkEvent::Undeliverable { source: 1, packet: Packet { destination: 255, x: 7, y: 8 } },
+        ]);
+        assert_eq!(network.step().unwrap(), vec![]);
+        assert_eq!(network.step().unwrap(), vec![]);
+        assert!(network.is_idle());
+    }
+
+    #[test]
+    fn test_nat() {
+        let program = assemble(RELAY).unwrap();
+        let mut network = Network::new(&program, 4);
+        network.set_nat(255);
+        let mut injected = Vec::new();
+        let event = network.run_until(|event| match event {
+            NetworkEvent::NatInjected(packet) => {
+                injected.push(packet.y);
+                injected.len() == 2
+            },
+            _ => false
+        }).unwrap();
+        assert_eq!(event, Some(NetworkEvent::NatInjected(Packet { destination: 255, x: 7, y: 8 })));
+        assert_eq!(injected, vec![8, 8]);
+    }
+}
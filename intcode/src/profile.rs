@@ -0,0 +1,211 @@
+use crate::instruction::{operation, split_opcode};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A backward jump taken while profiling: the loop runs from `start` (the
+/// jump target) to `end` (the jump instruction).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loop {
+    pub start: usize,
+    pub end: usize,
+    /// How many times the jump back was taken.
+    pub iterations: u64,
+    /// Instructions executed inside `start..=end`, over the whole run.
+    pub executions: u64
+}
+
+/// Per-address counts of instructions executed, data read and data written,
+/// collected by a machine with `IntComputer::set_profiler`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profiler {
+    executed: Vec<u64>,
+    operands: Vec<bool>,
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+    opcodes: BTreeMap<i64, u64>,
+    back_jumps: BTreeMap<(usize, usize), u64>
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Counts one executed instruction. `reads` are the addresses its
+    /// parameters were read from and `next` is where execution continued.
+    pub fn record(&mut self, position: usize, instruction: i64, reads: &[usize], write: Option<usize>, next: usize) {
+        let opcode = split_opcode(instruction).0;
+        bump(&mut self.executed, position);
+        *self.opcodes.entry(opcode).or_insert(0) += 1;
+        if let Some(op) = operation(opcode) {
+            for address in position + 1..=position + op.arity() {
+                mark(&mut self.operands, address);
+            }
+            if op.is_jump() && next <= position {
+                *self.back_jumps.entry((next, position)).or_insert(0) += 1;
+            }
+        }
+        for address in reads {
+            bump(&mut self.reads, *address);
+        }
+        if let Some(address) = write {
+            bump(&mut self.writes, address);
+        }
+    }
+
+    /// Adds the counts of `other`, e.g. a run of the same program on
+    /// another input.
+    pub fn merge(&mut self, other: &Profiler) {
+        add(&mut self.executed, &other.executed);
+        add(&mut self.reads, &other.reads);
+        add(&mut self.writes, &other.writes);
+        for (address, operand) in other.operands.iter().enumerate() {
+            if *operand {
+                mark(&mut self.operands, address);
+            }
+        }
+        for (opcode, count) in &other.opcodes {
+            *self.opcodes.entry(*opcode).or_insert(0) += count;
+        }
+        for (jump, count) in &other.back_jumps {
+            *self.back_jumps.entry(*jump).or_insert(0) += count;
+        }
+    }
+
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executed.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(address).copied().unwrap_or(0)
+    }
+
+    /// Instructions executed, by opcode.
+    pub fn opcode_counts(&self) -> &BTreeMap<i64, u64> {
+        &self.opcodes
+    }
+
+    /// The `count` loops that executed the most instructions.
+    pub fn hot_loops(&self, count: usize) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = self.back_jumps.iter()
+            .map(|(&(start, end), &iterations)| Loop {
+                start,
+                end,
+                iterations,
+                executions: (start..=end).map(|address| self.executions(address)).sum()
+            })
+            .collect();
+        loops.sort_by(|a, b| b.executions.cmp(&a.executions).then(a.start.cmp(&b.start)));
+        loops.truncate(count);
+        loops
+    }
+
+    /// One character per address of a `size`-word program, 64 to a line:
+    /// `x` for an executed instruction, `+` for its parameters, `w` for data
+    /// written, `r` for data only read and `.` for memory never touched.
+    pub fn coverage(&self, size: usize) -> String {
+        let mut map = String::new();
+        for start in (0..size).step_by(64) {
+            let _ = write!(map, "{:>5}: ", start);
+            for address in start..size.min(start + 64) {
+                map.push(if self.executions(address) > 0 {
+                    'x'
+                } else if self.operands.get(address) == Some(&true) {
+                    '+'
+                } else if self.writes(address) > 0 {
+                    'w'
+                } else if self.reads(address) > 0 {
+                    'r'
+                } else {
+                    '.'
+                });
+            }
+            map.push('\n');
+        }
+        map
+    }
+
+    /// Opcode totals, the `loops` hottest loops and the coverage map of a
+    /// `size`-word program.
+    pub fn report(&self, size: usize, loops: usize) -> String {
+        let mut report = String::from("opcodes:\n");
+        for (opcode, count) in &self.opcodes {
+            let mnemonic = operation(*opcode).map_or("?", |op| op.mnemonic);
+            let _ = writeln!(report, "  {:>4} {:<4} {}", opcode, mnemonic, count);
+        }
+        report.push_str("hot loops:\n");
+        for hot in self.hot_loops(loops) {
+            let _ = writeln!(report, "  {}..{} iterations={} instructions={}",
+                hot.start, hot.end, hot.iterations, hot.executions);
+        }
+        report.push_str("coverage:\n");
+        report.push_str(&self.coverage(size));
+        report
+    }
+}
+
+fn bump(counts: &mut Vec<u64>, address: usize) {
+    if counts.len() <= address {
+        counts.resize(address + 1, 0);
+    }
+    counts[address] += 1;
+}
+
+fn mark(flags: &mut Vec<bool>, address: usize) {
+    if flags.len() <= address {
+        flags.resize(address + 1, false);
+    }
+    flags[address] = true;
+}
+
+fn add(counts: &mut Vec<u64>, other: &[u64]) {
+    if counts.len() < other.len() {
+        counts.resize(other.len(), 0);
+    }
+    for (count, extra) in counts.iter_mut().zip(other) {
+        *count += extra;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntComputer;
+
+    // Counts down from the input, outputting each value.
+    const COUNTDOWN: [i64; 14] = [3,13,4,13,1001,13,-1,13,1005,13,2,99,0,0];
+
+    fn profile(input: i64) -> Profiler {
+        let mut ic = IntComputer::load(COUNTDOWN.to_vec());
+        ic.set_profiler(Profiler::new());
+        ic.write(input);
+        ic.execute().unwrap();
+        ic.take_profiler().unwrap()
+    }
+
+    #[test]
+    fn test_counts() {
+        let profiler = profile(3);
+        assert_eq!(profiler.executions(0), 1);
+        assert_eq!(profiler.executions(2), 3);
+        assert_eq!(profiler.writes(13), 4);
+        assert_eq!(profiler.reads(13), 9);
+        assert_eq!(profiler.opcode_counts().get(&5), Some(&3));
+        assert_eq!(profiler.hot_loops(5), vec![Loop { start: 2, end: 8, iterations: 2, executions: 9 }]);
+        assert_eq!(profiler.coverage(COUNTDOWN.len()), "    0: x+x+x+++x++x.w\n");
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut profiler = profile(1);
+        profiler.merge(&profile(2));
+        assert_eq!(profiler.executions(11), 2);
+        assert_eq!(profiler.opcode_counts().get(&4), Some(&3));
+        assert_eq!(profiler.hot_loops(1)[0].iterations, 1);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::instruction::{operation, split_opcode};
+use crate::MemoryWrite;
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+/// Which part of an executed instruction a write landed on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overwritten {
+    Opcode,
+    Operand
+}
+
+/// A write to an address that had already been executed as code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodeWrite {
+    /// Address of the writing instruction.
+    pub writer: usize,
+    pub address: usize,
+    /// Start of the executed instruction `address` belongs to.
+    pub instruction: usize,
+    pub overwritten: Overwritten,
+    pub old: i64,
+    pub new: i64
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "write [{}] = {} at {}, ", self.address, self.new, self.writer)?;
+        match self.overwritten {
+            Overwritten::Opcode => write!(f, "over the opcode at {}", self.instruction),
+            Overwritten::Operand => write!(f, "over an operand of the instruction at {}", self.instruction)
+        }
+    }
+}
+
+/// Tracks which addresses have been executed as code and records every
+/// write to them, for a machine with `IntComputer::set_code_tracker`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeTracker {
+    /// For each address, the start of the last instruction executed over it.
+    owners: Vec<Option<usize>>,
+    writes: Vec<CodeWrite>
+}
+
+impl CodeTracker {
+    pub fn new() -> CodeTracker {
+        CodeTracker::default()
+    }
+
+    /// Marks the instruction at `position` as executed, then records its
+    /// write if that landed on executed code.
+    pub(crate) fn record(&mut self, position: usize, instruction: i64, write: Option<&MemoryWrite>) {
+        let size = operation(split_opcode(instruction).0).map_or(1, |op| op.arity() + 1);
+        if self.owners.len() < position + size {
+            self.owners.resize(position + size, None);
+        }
+        for owner in &mut self.owners[position..position + size] {
+            *owner = Some(position);
+        }
+        if let Some(write) = write {
+            if let Some(Some(start)) = self.owners.get(write.address) {
+                let overwritten = if *start == write.address { Overwritten::Opcode } else { Overwritten::Operand };
+                self.writes.push(CodeWrite {
+                    writer: position,
+                    address: write.address,
+                    instruction: *start,
+                    overwritten,
+                    old: write.old,
+                    new: write.new
+                });
+            }
+        }
+    }
+
+    /// Whether an instruction covering `address` has been executed.
+    pub fn is_code(&self, address: usize) -> bool {
+        matches!(self.owners.get(address), Some(Some(_)))
+    }
+
+    pub fn writes(&self) -> &[CodeWrite] {
+        &self.writes
+    }
+
+    /// The writes to code grouped by writing instruction, one line each with
+    /// the addresses it wrote.
+    pub fn summary(&self) -> String {
+        let mut writers: BTreeMap<usize, (usize, Vec<&CodeWrite>)> = BTreeMap::new();
+        for write in &self.writes {
+            let (count, targets) = writers.entry(write.writer).or_insert((0, Vec::new()));
+            *count += 1;
+            if !targets.iter().any(|target| target.address == write.address) {
+                targets.push(write);
+            }
+        }
+        let plural = if self.writes.len() == 1 { "" } else { "s" };
+        let mut summary = format!("{} write{} to executed code\n", self.writes.len(), plural);
+        for (writer, (count, targets)) in writers {
+            let targets: Vec<String> = targets.iter().map(|target| {
+                let part = match target.overwritten {
+                    Overwritten::Opcode => "opcode",
+                    Overwritten::Operand => "operand"
+                };
+                format!("[{}] ({} of {})", target.address, part, target.instruction)
+            }).collect();
+            let plural = if count == 1 { "" } else { "s" };
+            let _ = writeln!(summary, "  instruction at {}: {} write{} to {}", writer, count, plural, targets.join(", "));
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntComputer;
+
+    #[test]
+    fn test_code_writes() {
+        // Bumps its own operand, then turns its first instruction into a
+        // halt and jumps back to it.
+        let mut ic = IntComputer::load(vec![1001,1,1,1,1101,99,0,0,1105,1,0]);
+        ic.set_code_tracker(CodeTracker::new());
+        ic.execute().unwrap();
+        let tracker = ic.code_tracker().unwrap();
+        assert_eq!(tracker.writes(), &[
+            CodeWrite { writer: 0, address: 1, instruction: 0, overwritten: Overwritten::Operand, old: 1, new: 2 },
+            CodeWrite { writer: 4, address: 0, instruction: 0, overwritten: Overwritten::Opcode, old: 1001, new: 99 },
+        ]);
+        assert_eq!(tracker.writes()[1].to_string(), "write [0] = 99 at 4, over the opcode at 0");
+        assert!(tracker.is_code(10));
+        assert!(!tracker.is_code(11));
+        assert_eq!(tracker.summary(), concat!(
+            "2 writes to executed code\n",
+            "  instruction at 0: 1 write to [1] (operand of 0)\n",
+            "  instruction at 4: 1 write to [0] (opcode of 0)\n"));
+    }
+}
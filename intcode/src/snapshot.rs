@@ -0,0 +1,253 @@
+use crate::{ErrorKind, ExecutionState, IntcodeError};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const HEADER: &str = "intcode-snapshot 2";
+
+/// Everything needed to resume an `IntComputer` exactly where it stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub memory: Vec<i64>,
+    pub position: usize,
+    pub relative_base: i64,
+    /// Instructions executed so far, which `execute_until` counts towards.
+    pub cycles: u64,
+    pub memory_limit: Option<usize>,
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+    pub execution_state: ExecutionState
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Parse { line: usize, message: String }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::Parse { line, message } => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> SnapshotError {
+        SnapshotError::Io(error)
+    }
+}
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, SnapshotError> {
+        Snapshot::read_from(File::open(path)?)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "position {}", self.position)?;
+        writeln!(writer, "relative_base {}", self.relative_base)?;
+        writeln!(writer, "cycles {}", self.cycles)?;
+        match self.memory_limit {
+            Some(limit) => writeln!(writer, "memory_limit {}", limit)?,
+            None => writeln!(writer, "memory_limit none")?
+        }
+        writeln!(writer, "state {}", format_state(&self.execution_state))?;
+        writeln!(writer, "input {}", join(&self.input))?;
+        writeln!(writer, "output {}", join(&self.output))?;
+        writeln!(writer, "memory {}", join(&self.memory))
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Snapshot, SnapshotError> {
+        let mut lines = Vec::new();
+        for line in BufReader::new(reader).lines() {
+            lines.push(line?);
+        }
+        if lines.first().map(|l| l.trim()) != Some(HEADER) {
+            return Err(SnapshotError::Parse { line: 1, message: format!("expected {:?}", HEADER) })
+        }
+        let field = |index: usize, name: &str| -> Result<&str, SnapshotError> {
+            let line = lines.get(index).map(|l| l.trim_end()).unwrap_or("");
+            let error = || SnapshotError::Parse { line: index + 1, message: format!("expected {}", name) };
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok(value),
+                None if line == name => Ok(""),
+                _ => Err(error())
+            }
+        };
+        let parse_error = |index: usize, message: String| SnapshotError::Parse { line: index + 1, message };
+
+        let position = parse_value(field(1, "position")?).map_err(|m| parse_error(1, m))?;
+        let relative_base = parse_value(field(2, "relative_base")?).map_err(|m| parse_error(2, m))?;
+        let cycles = parse_value(field(3, "cycles")?).map_err(|m| parse_error(3, m))?;
+        let memory_limit = match field(4, "memory_limit")? {
+            "none" => None,
+            limit => Some(parse_value(limit).map_err(|m| parse_error(4, m))?)
+        };
+        let execution_state = parse_state(field(5, "state")?).map_err(|m| parse_error(5, m))?;
+        let input = parse_list(field(6, "input")?).map_err(|m| parse_error(6, m))?;
+        let output = parse_list(field(7, "output")?).map_err(|m| parse_error(7, m))?;
+        let memory = parse_list(field(8, "memory")?).map_err(|m| parse_error(8, m))?;
+        Ok(Snapshot { memory, position, relative_base, cycles, memory_limit, input, output, execution_state })
+    }
+}
+
+fn join(values: &[i64]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+fn parse_value<T: FromStr>(text: &str) -> Result<T, String> {
+    text.trim().parse().map_err(|_| format!("invalid number {:?}", text))
+}
+
+fn parse_list(text: &str) -> Result<Vec<i64>, String> {
+    if text.trim().is_empty() {
+        return Ok(Vec::new())
+    }
+    text.split(',').map(parse_value).collect()
+}
+
+fn format_state(state: &ExecutionState) -> String {
+    match state {
+        ExecutionState::ReadyToRun => "ready".to_string(),
+        ExecutionState::Running => "running".to_string(),
+        ExecutionState::WaitingInput => "waiting".to_string(),
+        ExecutionState::Halted => "halted".to_string(),
+        ExecutionState::BudgetExhausted => "budget".to_string(),
+        ExecutionState::Paused => "paused".to_string(),
+        ExecutionState::FatalError(error) => {
+            let kind = match &error.kind {
+                ErrorKind::UnknownOpcode(opcode) => format!("unknown_opcode {}", opcode),
+                ErrorKind::InvalidMode { parameter, mode } => format!("invalid_mode {} {}", parameter, mode),
+                ErrorKind::NegativeAddress(address) => format!("negative_address {}", address),
+                ErrorKind::AddressOutOfRange { address, limit } => format!("out_of_range {} {}", address, limit),
+                ErrorKind::ImmediateWrite { parameter } => format!("immediate_write {}", parameter),
+                ErrorKind::ArithmeticOverflow => "overflow".to_string()
+            };
+            format!("error {} {} {}", error.position, error.instruction, kind)
+        }
+    }
+}
+
+fn parse_state(text: &str) -> Result<ExecutionState, String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let state = match words.as_slice() {
+        ["ready"] => ExecutionState::ReadyToRun,
+        ["running"] => ExecutionState::Running,
+        ["waiting"] => ExecutionState::WaitingInput,
+        ["halted"] => ExecutionState::Halted,
+        ["budget"] => ExecutionState::BudgetExhausted,
+        ["paused"] => ExecutionState::Paused,
+        ["error", position, instruction, kind @ ..] => {
+            let kind = match kind {
+                ["unknown_opcode", opcode] => ErrorKind::UnknownOpcode(parse_value(opcode)?),
+                ["invalid_mode", parameter, mode] =>
+                    ErrorKind::InvalidMode { parameter: parse_value(parameter)?, mode: parse_value(mode)? },
+                ["negative_address", address] => ErrorKind::NegativeAddress(parse_value(address)?),
+                ["out_of_range", address, limit] =>
+                    ErrorKind::AddressOutOfRange { address: parse_value(address)?, limit: parse_value(limit)? },
+                ["immediate_write", parameter] => ErrorKind::ImmediateWrite { parameter: parse_value(parameter)? },
+                ["overflow"] => ErrorKind::ArithmeticOverflow,
+                _ => return Err(format!("unknown error {:?}", kind.join(" ")))
+            };
+            ExecutionState::FatalError(IntcodeError {
+                position: parse_value(position)?,
+                instruction: parse_value(instruction)?,
+                kind
+            })
+        },
+        _ => return Err(format!("unknown state {:?}", text))
+    };
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntComputer;
+
+    #[test]
+    fn test_round_trip() {
+        let mut ic = IntComputer::load(vec![3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0]);
+        ic.set_memory_limit(64);
+        ic.write(4);
+        ic.execute().unwrap();
+        let snapshot = ic.snapshot();
+        assert_eq!(snapshot.execution_state, ExecutionState::WaitingInput);
+
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        let restored = Snapshot::read_from(&bytes[..]).unwrap();
+        assert_eq!(restored, snapshot);
+
+        let mut resumed = IntComputer::restore(&restored);
+        resumed.write(3);
+        resumed.execute().unwrap();
+        ic.write(3);
+        ic.execute().unwrap();
+        assert_eq!(resumed.snapshot(), ic.snapshot());
+        assert_eq!(resumed.read(), Some(34));
+    }
+
+    #[test]
+    fn test_cycles() {
+        let mut ic = IntComputer::load(vec![1001,7,1,7,1105,1,0,0]);
+        ic.execute_for(25).unwrap();
+        let mut bytes = Vec::new();
+        ic.snapshot().write_to(&mut bytes).unwrap();
+        let snapshot = Snapshot::read_from(&bytes[..]).unwrap();
+        assert_eq!(snapshot.cycles, 25);
+
+        let mut resumed = IntComputer::restore(&snapshot);
+        assert_eq!(resumed.cycles(), 25);
+        resumed.execute_until(40).unwrap();
+        ic.execute_until(40).unwrap();
+        assert_eq!(resumed.snapshot(), ic.snapshot());
+        assert_eq!(resumed.value(7), 20);
+    }
+
+    #[test]
+    fn test_error_state() {
+        let mut ic = IntComputer::load(vec![109,-3,22201,0,0,0,99]);
+        ic.execute().unwrap_err();
+        let snapshot = ic.snapshot();
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        assert_eq!(Snapshot::read_from(&bytes[..]).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_file() {
+        let path = std::env::temp_dir().join(format!("intcode-snapshot-{}.txt", std::process::id()));
+        let mut ic = IntComputer::load(vec![104,7,3,0,99]);
+        ic.execute().unwrap();
+        ic.snapshot().save(&path).unwrap();
+        let snapshot = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot.output, vec![7]);
+        assert_eq!(snapshot.position, 2);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let text = "intcode-snapshot 2\nposition 0\nrelative_base x\n";
+        match Snapshot::read_from(text.as_bytes()) {
+            Err(SnapshotError::Parse { line: 3, .. }) => (),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+}
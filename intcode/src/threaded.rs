@@ -0,0 +1,156 @@
+use crate::{ExecutionState, IntComputer, IntcodeError};
+
+use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::thread::{self, JoinHandle};
+
+pub type ThreadedComputer = IntComputer<Receiver<i64>, Sender<i64>>;
+
+/// Runs a machine on the current thread until it halts, fails, or its input
+/// channel is closed with nothing left to read (reported as `WaitingInput`).
+pub fn run(mut computer: ThreadedComputer) -> Result<ExecutionState, IntcodeError> {
+    computer.execute().cloned()
+}
+
+/// Spawns `program` on its own thread, reading from `input` and writing to
+/// `output`. Dropping the machine's channels when it stops lets peers wired
+/// to it shut down in turn.
+pub fn spawn_connected(program: Vec<i64>, input: Receiver<i64>, output: Sender<i64>)
+    -> JoinHandle<Result<ExecutionState, IntcodeError>> {
+    thread::spawn(move || run(IntComputer::with_io(program, input, output)))
+}
+
+/// A machine running on its own thread, with channels to feed it input and
+/// collect its output.
+pub struct MachineHandle {
+    input: Option<Sender<i64>>,
+    output: Receiver<i64>,
+    thread: JoinHandle<Result<ExecutionState, IntcodeError>>
+}
+
+pub fn spawn(program: Vec<i64>) -> MachineHandle {
+    let (input, machine_input) = channel();
+    let (machine_output, output) = channel();
+    MachineHandle {
+        input: Some(input),
+        output,
+        thread: spawn_connected(program, machine_input, machine_output)
+    }
+}
+
+impl MachineHandle {
+    /// Queues an input value. Fails once the machine has stopped reading
+    /// or `close_input` has been called.
+    pub fn send(&self, value: i64) -> Result<(), SendError<i64>> {
+        match &self.input {
+            Some(input) => input.send(value),
+            None => Err(SendError(value))
+        }
+    }
+
+    /// Blocks for the next output value; `None` once the machine has
+    /// stopped and all its output has been read.
+    pub fn recv(&self) -> Option<i64> {
+        self.output.recv().ok()
+    }
+
+    pub fn input(&self) -> Option<&Sender<i64>> {
+        self.input.as_ref()
+    }
+
+    pub fn output(&self) -> &Receiver<i64> {
+        &self.output
+    }
+
+    /// Signals that no more input will come, so a machine blocked on input
+    /// stops with `WaitingInput` instead of waiting forever.
+    pub fn close_input(&mut self) {
+        self.input = None;
+    }
+
+    /// Closes the input and waits for the machine to stop. Output not yet
+    /// received is discarded.
+    pub fn join(mut self) -> Result<ExecutionState, IntcodeError> {
+        self.close_input();
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic)
+        }
+    }
+}
+
+/// Receives from `source`, forwarding every value to `sink` until `source`
+/// disconnects. Returns the last value seen. Sends after `sink` hangs up
+/// are dropped.
+pub fn forward(source: &Receiver<i64>, sink: &Sender<i64>) -> Option<i64> {
+    let mut last = None;
+    while let Ok(value) = source.recv() {
+        let _ = sink.send(value);
+        last = Some(value);
+    }
+    last
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn() {
+        let machine = spawn(vec![3,9,8,9,10,9,4,9,99,-1,8]);
+        machine.send(8).unwrap();
+        assert_eq!(machine.recv(), Some(1));
+        assert_eq!(machine.recv(), None);
+        assert_eq!(machine.join(), Ok(ExecutionState::Halted));
+    }
+
+    #[test]
+    fn test_close_input() {
+        let mut machine = spawn(vec![3,7,4,7,1105,1,0,0]);
+        machine.send(1).unwrap();
+        machine.send(2).unwrap();
+        assert_eq!(machine.recv(), Some(1));
+        assert_eq!(machine.recv(), Some(2));
+        machine.close_input();
+        assert_eq!(machine.recv(), None);
+        assert_eq!(machine.join(), Ok(ExecutionState::WaitingInput));
+    }
+
+    #[test]
+    fn test_error() {
+        let machine = spawn(vec![3,0,42]);
+        machine.send(1).unwrap();
+        assert_eq!(machine.join().unwrap_err().position, 2);
+    }
+
+    #[test]
+    fn test_feedback_ring() {
+        let program = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,
+            27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
+        let phases = vec![9,8,7,6,5];
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| channel()).unzip();
+        let (tap, tapped) = channel();
+        for (sender, phase) in senders.iter().zip(&phases) {
+            sender.send(*phase).unwrap();
+        }
+        senders[0].send(0).unwrap();
+
+        let mut threads = Vec::new();
+        for (index, input) in receivers.into_iter().enumerate() {
+            let output = match senders.get(index + 1) {
+                Some(next) => next.clone(),
+                None => tap.clone()
+            };
+            threads.push(spawn_connected(program.clone(), input, output));
+        }
+        let first = senders[0].clone();
+        drop(senders);
+        drop(tap);
+
+        assert_eq!(forward(&tapped, &first), Some(139629729));
+        drop(first);
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), Ok(ExecutionState::Halted));
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{ExecutionState, IntComputer, IntcodeError};
+
+use std::fmt;
+
+pub type NodeId = usize;
+pub type SinkId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Endpoint {
+    Node(NodeId),
+    Sink(SinkId)
+}
+
+const DEFAULT_TIME_SLICE: usize = 10_000;
+
+/// A dataflow graph of intcode machines. Every value a node outputs is
+/// copied along each of its outgoing edges, so edges can fan out; several
+/// edges into one node fan in, in the order the values were produced.
+pub struct Topology {
+    nodes: Vec<IntComputer>,
+    edges: Vec<Vec<Endpoint>>,
+    sinks: usize,
+    time_slice: usize,
+    livelock_rounds: Option<usize>
+}
+
+impl Default for Topology {
+    fn default() -> Topology {
+        Topology {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            sinks: 0,
+            time_slice: DEFAULT_TIME_SLICE,
+            livelock_rounds: None
+        }
+    }
+}
+
+/// A machine that could not finish, and where it was stuck.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StuckNode {
+    pub node: NodeId,
+    pub position: usize,
+    pub state: ExecutionState
+}
+
+/// Why a run stopped before every node halted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stall {
+    /// Every unfinished node is waiting for input that nobody will send.
+    Deadlock(Vec<StuckNode>),
+    /// Nodes kept running but no value moved and no node stopped for the
+    /// configured number of rounds.
+    Livelock(Vec<StuckNode>)
+}
+
+impl fmt::Display for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, nodes) = match self {
+            Stall::Deadlock(nodes) => ("deadlock", nodes),
+            Stall::Livelock(nodes) => ("livelock", nodes)
+        };
+        let nodes: Vec<String> = nodes.iter()
+            .map(|n| format!("node {} at position {}", n.node, n.position))
+            .collect();
+        write!(f, "{}: {}", kind, nodes.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyResult {
+    /// Final state of each node, indexed by `NodeId`.
+    pub states: Vec<ExecutionState>,
+    /// Values that reached each sink, indexed by `SinkId`.
+    pub sinks: Vec<Vec<i64>>,
+    /// Set when the run ended with nodes that had not halted or failed.
+    pub stall: Option<Stall>
+}
+
+impl TopologyResult {
+    /// The first node failure, if any node stopped on an error.
+    pub fn error(&self) -> Option<&IntcodeError> {
+        self.states.iter().find_map(|state| match state {
+            ExecutionState::FatalError(error) => Some(error),
+            _ => None
+        })
+    }
+}
+
+impl Topology {
+    pub fn new() -> Topology {
+        Topology::default()
+    }
+
+    /// Adds `machine` with `inputs` (e.g. a phase setting) queued after
+    /// any it already has. Nodes running the same program can be clones of
+    /// one loaded machine.
+    pub fn add_node(&mut self, mut machine: IntComputer, inputs: &[i64]) -> NodeId {
+        for input in inputs {
+            machine.write(*input);
+        }
+        self.nodes.push(machine);
+        self.edges.push(Vec::new());
+        self.nodes.len() - 1
+    }
+
+    /// Sends everything `from` outputs to `to`'s input.
+    pub fn connect(&mut self, from: NodeId, to: NodeId) {
+        self.edges[from].push(Endpoint::Node(to));
+    }
+
+    /// Maximum number of instructions a node runs per turn. Defaults to
+    /// 10000.
+    pub fn set_time_slice(&mut self, steps: usize) {
+        self.time_slice = steps.max(1);
+    }
+
+    /// Stops the run and reports a livelock after `rounds` rounds in a row
+    /// without progress, that is once every running node has gone `rounds`
+    /// time slices without any input or output. Off by default, since a
+    /// long computation between two values looks exactly the same.
+    pub fn set_livelock_rounds(&mut self, rounds: usize) {
+        self.livelock_rounds = Some(rounds.max(1));
+    }
+
+    /// Collects everything `from` outputs into a sink reported by `run`.
+    pub fn add_sink(&mut self, from: NodeId) -> SinkId {
+        self.edges[from].push(Endpoint::Sink(self.sinks));
+        self.sinks += 1;
+        self.sinks - 1
+    }
+
+    /// Runs nodes round-robin, each for at most one time slice per turn,
+    /// until every node has halted or failed, or the graph stalls.
+    pub fn run(mut self) -> TopologyResult {
+        let mut sinks = vec![Vec::new(); self.sinks];
+        let mut ready = vec![true; self.nodes.len()];
+        let mut idle_rounds = 0;
+        let mut livelock = false;
+        while ready.iter().any(|r| *r) {
+            let mut progress = false;
+            for id in 0..self.nodes.len() {
+                if !ready[id] {
+                    continue
+                }
+                let pending = self.nodes[id].input().len();
+                let state = self.nodes[id].execute_for(self.time_slice as u64).cloned()
+                    .unwrap_or_else(ExecutionState::FatalError);
+                let exhausted = state == ExecutionState::BudgetExhausted;
+                ready[id] = exhausted;
+                progress |= !exhausted || self.nodes[id].input().len() != pending;
+                while let Some(value) = self.nodes[id].read() {
+                    progress = true;
+                    for endpoint in &self.edges[id] {
+                        match *endpoint {
+                            Endpoint::Node(to) => {
+                                self.nodes[to].write(value);
+                                ready[to] = true;
+                            },
+                            Endpoint::Sink(sink) => sinks[sink].push(value)
+                        }
+                    }
+                }
+            }
+            idle_rounds = if progress { 0 } else { idle_rounds + 1 };
+            if self.livelock_rounds.is_some_and(|rounds| idle_rounds >= rounds) {
+                livelock = true;
+                break
+            }
+        }
+        let stuck: Vec<StuckNode> = self.nodes.iter().enumerate()
+            .filter(|(_, node)| !matches!(node.state(), ExecutionState::Halted | ExecutionState::FatalError(_)))
+            .map(|(node, ic)| StuckNode { node, position: ic.position(), state: ic.state().clone() })
+            .collect();
+        let stall = match (stuck.is_empty(), livelock) {
+            (true, _) => None,
+            (false, true) => Some(Stall::Livelock(stuck)),
+            (false, false) => Some(Stall::Deadlock(stuck))
+        };
+        TopologyResult {
+            states: self.nodes.iter().map(|node| node.state().clone()).collect(),
+            sinks,
+            stall
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOUBLE: [i64; 12] = [3,11,1002,11,2,11,4,11,1105,1,0,0];
+    const SUM_PAIRS: [i64; 15] = [3,13,3,14,1,13,14,13,4,13,1105,1,0,0,0];
+
+    fn machine(program: &[i64]) -> IntComputer {
+        IntComputer::load(program.to_vec())
+    }
+
+    #[test]
+    fn test_fan_out_fan_in() {
+        let mut topology = Topology::new();
+        let source = topology.add_node(machine(&DOUBLE), &[1, 2, 3]);
+        let left = topology.add_node(machine(&DOUBLE), &[]);
+        let right = topology.add_node(machine(&DOUBLE), &[]);
+        let sum = topology.add_node(machine(&SUM_PAIRS), &[]);
+        topology.connect(source, left);
+        topology.connect(source, right);
+        topology.connect(left, sum);
+        topology.connect(right, sum);
+        let doubled = topology.add_sink(source);
+        let summed = topology.add_sink(sum);
+        let result = topology.run();
+        assert_eq!(result.sinks[doubled], vec![2, 4, 6]);
+        assert_eq!(result.sinks[summed], vec![12, 16, 20]);
+        assert_eq!(result.states, vec![ExecutionState::WaitingInput; 4]);
+        assert_eq!(result.error(), None);
+        assert_eq!(result.stall, Some(Stall::Deadlock((0..4).map(|node| StuckNode {
+            node, position: 0, state: ExecutionState::WaitingInput
+        }).collect())));
+    }
+
+    #[test]
+    fn test_ring() {
+        let program = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,
+            27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
+        let amplifier = machine(&program);
+        let mut topology = Topology::new();
+        let mut nodes = vec![topology.add_node(amplifier.clone(), &[9, 0])];
+        for phase in &[8,7,6,5] {
+            nodes.push(topology.add_node(amplifier.clone(), &[*phase]));
+        }
+        for pair in nodes.windows(2) {
+            topology.connect(pair[0], pair[1]);
+        }
+        topology.connect(nodes[4], nodes[0]);
+        let thrust = topology.add_sink(nodes[4]);
+        let result = topology.run();
+        assert_eq!(result.sinks[thrust].last(), Some(&139629729));
+        assert_eq!(result.states, vec![ExecutionState::Halted; 5]);
+        assert_eq!(result.stall, None);
+    }
+
+    #[test]
+    fn test_deadlock() {
+        // Each node waits for the other before sending anything.
+        let mut topology = Topology::new();
+        let a = topology.add_node(machine(&[3,7,4,7,99,0,0,0]), &[]);
+        let b = topology.add_node(machine(&[3,7,4,7,99,0,0,0]), &[]);
+        let done = topology.add_node(machine(&[104,1,99]), &[]);
+        topology.connect(a, b);
+        topology.connect(b, a);
+        let result = topology.run();
+        assert_eq!(result.states[done], ExecutionState::Halted);
+        assert_eq!(result.stall, Some(Stall::Deadlock(vec![
+            StuckNode { node: a, position: 0, state: ExecutionState::WaitingInput },
+            StuckNode { node: b, position: 0, state: ExecutionState::WaitingInput },
+        ])));
+        assert_eq!(result.stall.unwrap().to_string(), "deadlock: node 0 at position 0, node 1 at position 0");
+    }
+
+    #[test]
+    fn test_livelock() {
+        let mut topology = Topology::new();
+        let spinner = topology.add_node(machine(&[1105,1,0]), &[]);
+        let waiter = topology.add_node(machine(&[3,0,99]), &[]);
+        topology.connect(spinner, waiter);
+        topology.set_time_slice(50);
+        topology.set_livelock_rounds(3);
+        let result = topology.run();
+        assert_eq!(result.stall, Some(Stall::Livelock(vec![
+            StuckNode { node: spinner, position: 0, state: ExecutionState::BudgetExhausted },
+            StuckNode { node: waiter, position: 0, state: ExecutionState::WaitingInput },
+        ])));
+    }
+
+    #[test]
+    fn test_long_computation() {
+        // Counts down from 1000 for 200 slices before its only output.
+        let mut topology = Topology::new();
+        let counter = topology.add_node(machine(&[3,12,1001,12,-1,12,1005,12,2,104,0,99,0]), &[1000]);
+        let done = topology.add_sink(counter);
+        topology.set_time_slice(10);
+        let result = topology.run();
+        assert_eq!(result.sinks[done], vec![0]);
+        assert_eq!(result.stall, None);
+    }
+
+    #[test]
+    fn test_error() {
+        let mut topology = Topology::new();
+        let source = topology.add_node(machine(&[104,7,99]), &[]);
+        let broken = topology.add_node(machine(&[3,0,42]), &[]);
+        topology.connect(source, broken);
+        let result = topology.run();
+        assert_eq!(result.states[source], ExecutionState::Halted);
+        assert_eq!(result.error().map(|e| e.position), Some(2));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::instruction::split_opcode;
+use crate::MemoryWrite;
+
+use std::fmt;
+use std::io::{self, BufWriter, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    /// One line per instruction, e.g. `4 1001 op=1 modes=010 args=9,10 write=3:5->15`.
+    Text,
+    /// Zigzag LEB128 varints: position, raw instruction, operand count,
+    /// operands, then a write flag optionally followed by address, old, new.
+    Binary
+}
+
+/// One executed instruction: where it ran, the resolved values of the
+/// parameters it read, and the memory cell it wrote, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    pub position: usize,
+    pub instruction: i64,
+    pub operands: Vec<i64>,
+    pub write: Option<MemoryWrite>
+}
+
+impl TraceRecord {
+    pub fn opcode(&self) -> i64 {
+        split_opcode(self.instruction).0
+    }
+
+    pub fn modes(&self) -> [i64; 3] {
+        let (_, mode1, mode2, mode3) = split_opcode(self.instruction);
+        [mode1, mode2, mode3]
+    }
+
+    pub fn write_binary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_varint(writer, self.position as i64)?;
+        write_varint(writer, self.instruction)?;
+        write_varint(writer, self.operands.len() as i64)?;
+        for operand in &self.operands {
+            write_varint(writer, *operand)?;
+        }
+        match &self.write {
+            Some(write) => {
+                write_varint(writer, 1)?;
+                write_varint(writer, write.address as i64)?;
+                write_varint(writer, write.old)?;
+                write_varint(writer, write.new)
+            },
+            None => write_varint(writer, 0)
+        }
+    }
+
+    /// Reads the next record of a binary trace, or `None` at end of input.
+    pub fn read_binary<R: Read>(reader: &mut R) -> io::Result<Option<TraceRecord>> {
+        let position = match read_varint(reader)? {
+            Some(position) => position as usize,
+            None => return Ok(None)
+        };
+        let instruction = expect_varint(reader)?;
+        let count = expect_varint(reader)?;
+        let mut operands = Vec::new();
+        for _ in 0..count {
+            operands.push(expect_varint(reader)?);
+        }
+        let write = match expect_varint(reader)? {
+            0 => None,
+            _ => Some(MemoryWrite {
+                address: expect_varint(reader)? as usize,
+                old: expect_varint(reader)?,
+                new: expect_varint(reader)?
+            })
+        };
+        Ok(Some(TraceRecord { position, instruction, operands, write }))
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [mode1, mode2, mode3] = self.modes();
+        let operands: Vec<String> = self.operands.iter().map(|o| o.to_string()).collect();
+        write!(f, "{} {} op={} modes={}{}{} args={}",
+            self.position, self.instruction, self.opcode(), mode1, mode2, mode3, operands.join(","))?;
+        if let Some(write) = &self.write {
+            write!(f, " write={}:{}->{}", write.address, write.old, write.new)?;
+        }
+        Ok(())
+    }
+}
+
+/// Streams trace records to a writer through a buffer. The first I/O error
+/// stops tracing and is reported by `finish`, which also flushes the
+/// buffer; dropping the tracer instead flushes it and ignores any error.
+pub struct Tracer {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    format: TraceFormat,
+    error: Option<io::Error>
+}
+
+impl Tracer {
+    pub fn new<W: Write + Send + 'static>(writer: W, format: TraceFormat) -> Tracer {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        Tracer { writer: BufWriter::new(writer), format, error: None }
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return
+        }
+        let result = match self.format {
+            TraceFormat::Text => writeln!(self.writer, "{}", record),
+            TraceFormat::Binary => record.write_binary(&mut self.writer)
+        };
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush()
+        }
+    }
+}
+
+fn write_varint<W: Write>(writer: &mut W, value: i64) -> io::Result<()> {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+    let mut bytes = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (zigzag & 0x7f) as u8;
+        zigzag >>= 7;
+        if zigzag == 0 {
+            bytes[len] = byte;
+            return writer.write_all(&bytes[..=len])
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<Option<i64>> {
+    let mut zigzag: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None)
+            }
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated varint"))
+        }
+        if shift >= 64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "varint too long"))
+        }
+        zigzag |= ((byte[0] & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64)))
+        }
+    }
+}
+
+fn expect_varint<R: Read>(reader: &mut R) -> io::Result<i64> {
+    read_varint(reader)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated trace record"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntComputer;
+
+    use std::sync::{Arc, Mutex};
+
+    /// Collects what is written, counting the calls to `write`.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>, Arc<Mutex<usize>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            *self.1.lock().unwrap() += 1;
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_traced(program: Vec<i64>, input: i64, format: TraceFormat) -> Vec<u8> {
+        let buffer = SharedBuffer::default();
+        let mut ic = IntComputer::load(program);
+        ic.set_tracer(Tracer::new(buffer.clone(), format));
+        ic.write(input);
+        ic.execute().unwrap();
+        ic.take_tracer().unwrap().finish().unwrap();
+        let bytes = buffer.0.lock().unwrap().clone();
+        bytes
+    }
+
+    #[test]
+    fn test_text_trace() {
+        let trace = run_traced(vec![3,9,8,9,10,9,4,9,99,-1,8], 8, TraceFormat::Text);
+        assert_eq!(String::from_utf8(trace).unwrap(), "\
+0 3 op=3 modes=000 args= write=9:-1->8
+2 8 op=8 modes=000 args=8,8 write=9:8->1
+6 4 op=4 modes=000 args=1
+8 99 op=99 modes=000 args=
+");
+    }
+
+    #[test]
+    fn test_binary_trace() {
+        let trace = run_traced(vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1], -300, TraceFormat::Binary);
+        let mut reader = &trace[..];
+        let mut records = Vec::new();
+        while let Some(record) = TraceRecord::read_binary(&mut reader).unwrap() {
+            records.push(record);
+        }
+        assert_eq!(records, vec![
+            TraceRecord { position: 0, instruction: 3, operands: vec![],
+                write: Some(MemoryWrite { address: 3, old: -1, new: -300 }) },
+            TraceRecord { position: 2, instruction: 1105, operands: vec![-300, 9], write: None },
+            TraceRecord { position: 9, instruction: 4, operands: vec![1], write: None },
+            TraceRecord { position: 11, instruction: 99, operands: vec![], write: None },
+        ]);
+        assert_eq!(records[1].modes(), [1,1,0]);
+    }
+
+    #[test]
+    fn test_buffered() {
+        let buffer = SharedBuffer::default();
+        let mut ic = IntComputer::load(vec![1105,1,0]);
+        ic.set_tracer(Tracer::new(buffer.clone(), TraceFormat::Binary));
+        ic.execute_for(100).unwrap();
+        assert_eq!(*buffer.1.lock().unwrap(), 0);
+        drop(ic);
+        assert_eq!(*buffer.1.lock().unwrap(), 1);
+        assert_eq!(buffer.0.lock().unwrap().len(), 100 * 7);
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in vec![0, 1, -1, 63, -64, 64, 1 << 40, i64::MAX, i64::MIN] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value).unwrap();
+            assert_eq!(read_varint(&mut &bytes[..]).unwrap(), Some(value));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    /// A write that stores this value.
+    WriteValue(i64)
+}
+
+/// Watches the addresses `start..=end` for an access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub access: Access
+}
+
+impl Watchpoint {
+    pub fn new(addresses: RangeInclusive<usize>, access: Access) -> Watchpoint {
+        Watchpoint { start: *addresses.start(), end: *addresses.end(), access }
+    }
+
+    pub fn address(address: usize, access: Access) -> Watchpoint {
+        Watchpoint::new(address..=address, access)
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        self.start <= address && address <= self.end
+    }
+
+    fn read(&self, address: usize) -> bool {
+        self.access == Access::Read && self.contains(address)
+    }
+
+    fn written(&self, address: usize, value: i64) -> bool {
+        match self.access {
+            Access::Read => false,
+            Access::Write => self.contains(address),
+            Access::WriteValue(expected) => expected == value && self.contains(address)
+        }
+    }
+}
+
+/// A watched access: the instruction at `position` read or wrote `value`
+/// at `address`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub position: usize,
+    pub address: usize,
+    pub value: i64
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.watchpoint.access {
+            Access::Read => "read",
+            _ => "write"
+        };
+        write!(f, "{} [{}] = {} at {}", access, self.address, self.value, self.position)
+    }
+}
+
+/// What a machine does when a watchpoint fires. Either way the accessing
+/// instruction completes first.
+pub enum WatchAction {
+    /// Stop with `ExecutionState::Paused`; the hit is kept in `watch_hit`.
+    Pause,
+    Callback(Box<dyn FnMut(&WatchHit) + Send>)
+}
+
+/// Hits for `watchpoint` among the cells an instruction read and the one it
+/// wrote.
+pub(crate) fn hits(watchpoint: &Watchpoint, position: usize, reads: &[(usize, i64)], write: Option<(usize, i64)>)
+    -> Vec<WatchHit> {
+    let hit = |(address, value): (usize, i64)| WatchHit { watchpoint: *watchpoint, position, address, value };
+    let mut hits: Vec<WatchHit> = reads.iter()
+        .filter(|(address, _)| watchpoint.read(*address))
+        .map(|read| hit(*read))
+        .collect();
+    if let Some((address, value)) = write {
+        if watchpoint.written(address, value) {
+            hits.push(hit((address, value)));
+        }
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecutionState, IntComputer};
+
+    use std::sync::{Arc, Mutex};
+
+    // Patches its own `add` at address 4 into a `mul`, which squares [9];
+    // that also rewrites the operand of the `out` at 8 to point at [81].
+    const PATCHER: [i64; 11] = [1101,1,1,4,1,9,9,9,4,9,99];
+
+    #[test]
+    fn test_pause_on_write() {
+        let mut ic = IntComputer::load(vec![1101,1,1,4,1101,3,4,9,104,0,99]);
+        ic.add_watchpoint(Watchpoint::new(4..=7, Access::Write), WatchAction::Pause);
+        assert_eq!(ic.execute(), Ok(&ExecutionState::Paused));
+        assert_eq!(ic.position(), 4);
+        assert_eq!(ic.watch_hit().map(|hit| hit.to_string()), Some("write [4] = 2 at 0".to_string()));
+        assert_eq!(ic.execute(), Ok(&ExecutionState::Halted));
+        assert_eq!(ic.memory()[9], 8);
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let mut ic = IntComputer::load(PATCHER.to_vec());
+        for access in &[Access::Read, Access::WriteValue(2), Access::WriteValue(5)] {
+            let hits = hits.clone();
+            ic.add_watchpoint(Watchpoint::new(4..=9, *access), WatchAction::Callback(Box::new(move |hit| {
+                hits.lock().unwrap().push(*hit);
+            })));
+        }
+        assert_eq!(ic.execute(), Ok(&ExecutionState::Halted));
+        let hits: Vec<(Access, usize, usize, i64)> = hits.lock().unwrap().iter()
+            .map(|hit| (hit.watchpoint.access, hit.position, hit.address, hit.value))
+            .collect();
+        assert_eq!(hits, vec![
+            (Access::WriteValue(2), 0, 4, 2),
+            (Access::Read, 4, 9, 9),
+            (Access::Read, 4, 9, 9),
+        ]);
+    }
+}